@@ -0,0 +1,298 @@
+//! Support for reading the GGUF container format.
+//!
+//! Unlike the legacy GGML/GGJT formats, GGUF stores its hyperparameters and vocabulary as a
+//! flat, self-describing key-value metadata section rather than as positional fields, parsed
+//! here into [`GgufMetadata`]. Only the model's [`FileType`] is derived from that metadata
+//! ([`GgufMetadata::file_type`]); [`crate::Hyperparameters`] and the vocabulary are not read
+//! from it.
+
+use std::{collections::HashMap, io::BufRead};
+
+use crate::{util, FileType, LoadError};
+
+/// The key under which the model's [`FileType`] is recorded in a GGUF file's metadata.
+pub const FILE_TYPE_KEY: &str = "general.file_type";
+
+/// The largest string (or array element count) this parser will allocate for in one go.
+///
+/// A legitimate GGUF file never approaches this; a length anywhere near it almost always means
+/// the file is truncated or corrupt and the "length" field is being read from garbage bytes.
+/// Rejecting it up front avoids both a confusing downstream I/O error and an allocation sized by
+/// an arbitrary 64-bit length read from garbage bytes.
+const MAX_GGUF_ALLOCATION: u64 = 1 << 32;
+
+/// A single typed value from a GGUF metadata key-value pair.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    /// An unsigned 8-bit integer.
+    U8(u8),
+    /// A signed 8-bit integer.
+    I8(i8),
+    /// An unsigned 16-bit integer.
+    U16(u16),
+    /// A signed 16-bit integer.
+    I16(i16),
+    /// An unsigned 32-bit integer.
+    U32(u32),
+    /// A signed 32-bit integer.
+    I32(i32),
+    /// An unsigned 64-bit integer.
+    U64(u64),
+    /// A signed 64-bit integer.
+    I64(i64),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+    /// A boolean.
+    Bool(bool),
+    /// A UTF-8 string.
+    String(String),
+    /// A homogeneous array of values.
+    Array(Vec<GgufValue>),
+}
+
+/// The wire tag used to identify a [`GgufValue`]'s variant within the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+enum GgufValueType {
+    U8 = 0,
+    I8 = 1,
+    U16 = 2,
+    I16 = 3,
+    U32 = 4,
+    I32 = 5,
+    F32 = 6,
+    Bool = 7,
+    String = 8,
+    Array = 9,
+    U64 = 10,
+    I64 = 11,
+    F64 = 12,
+}
+impl TryFrom<u32> for GgufValueType {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::U8,
+            1 => Self::I8,
+            2 => Self::U16,
+            3 => Self::I16,
+            4 => Self::U32,
+            5 => Self::I32,
+            6 => Self::F32,
+            7 => Self::Bool,
+            8 => Self::String,
+            9 => Self::Array,
+            10 => Self::U64,
+            11 => Self::I64,
+            12 => Self::F64,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// The flattened key-value metadata section of a GGUF file.
+///
+/// Values are looked up by key with [`GgufMetadata::get`]; [`GgufMetadata::file_type`] is the
+/// only value this crate derives from it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GgufMetadata {
+    entries: HashMap<String, GgufValue>,
+}
+impl GgufMetadata {
+    /// Looks up a metadata value by its dotted key (e.g. `"llama.context_length"`).
+    pub fn get(&self, key: &str) -> Option<&GgufValue> {
+        self.entries.get(key)
+    }
+
+    /// Derives the model's [`FileType`] from the `general.file_type` metadata key, if present.
+    pub fn file_type(&self) -> Option<FileType> {
+        match self.entries.get(FILE_TYPE_KEY)? {
+            &GgufValue::U32(value) => FileType::try_from(value as i32).ok(),
+            &GgufValue::I32(value) => FileType::try_from(value).ok(),
+            &GgufValue::U64(value) => FileType::try_from(i32::try_from(value).ok()?).ok(),
+            &GgufValue::I64(value) => FileType::try_from(i32::try_from(value).ok()?).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Information about a single tensor, as recorded in the GGUF tensor directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufTensorInfo {
+    /// The tensor's name.
+    pub name: String,
+    /// The tensor's dimensions, outermost first.
+    pub dims: Vec<u64>,
+    /// The ggml element type the tensor's data is stored as.
+    pub element_type: i32,
+    /// The tensor's data offset: relative to the start of the (aligned) data section as read by
+    /// [`read_tensor_infos`], and an absolute file offset once rebased by
+    /// [`crate::loader::read_container_header`] or built by the legacy tensor directory reader.
+    pub offset: u64,
+}
+
+fn read_gguf_string(reader: &mut impl BufRead) -> Result<String, LoadError> {
+    let len = util::read_u64(reader)?;
+    if len > MAX_GGUF_ALLOCATION {
+        return Err(LoadError::InvalidGgufMetadata {
+            message: format!("string length {len} is implausibly large; file is likely truncated or corrupt"),
+        });
+    }
+    let len = usize::try_from(len)?;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).map_err(|e| LoadError::ReadExactFailed {
+        source: e,
+        bytes: len,
+    })?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn read_gguf_value(reader: &mut impl BufRead, value_type: GgufValueType) -> Result<GgufValue, LoadError> {
+    Ok(match value_type {
+        GgufValueType::U8 => GgufValue::U8(util::read_u8(reader)?),
+        GgufValueType::I8 => GgufValue::I8(util::read_i8(reader)?),
+        GgufValueType::U16 => GgufValue::U16(util::read_u16(reader)?),
+        GgufValueType::I16 => GgufValue::I16(util::read_i16(reader)?),
+        GgufValueType::U32 => GgufValue::U32(util::read_u32(reader)?),
+        GgufValueType::I32 => GgufValue::I32(util::read_i32(reader)?),
+        GgufValueType::F32 => GgufValue::F32(util::read_f32(reader)?),
+        GgufValueType::F64 => GgufValue::F64(util::read_f64(reader)?),
+        GgufValueType::U64 => GgufValue::U64(util::read_u64(reader)?),
+        GgufValueType::I64 => GgufValue::I64(util::read_i64(reader)?),
+        GgufValueType::Bool => GgufValue::Bool(util::read_u8(reader)? != 0),
+        GgufValueType::String => GgufValue::String(read_gguf_string(reader)?),
+        GgufValueType::Array => {
+            let element_tag = util::read_u32(reader)?;
+            let element_type = GgufValueType::try_from(element_tag)
+                .map_err(|_| LoadError::UnknownGgufValueType { value_type: element_tag })?;
+            let len = util::read_u64(reader)?;
+            if len > MAX_GGUF_ALLOCATION {
+                return Err(LoadError::InvalidGgufMetadata {
+                    message: format!(
+                        "array length {len} is implausibly large; file is likely truncated or corrupt"
+                    ),
+                });
+            }
+            let mut values = Vec::with_capacity(usize::try_from(len)?);
+            for _ in 0..len {
+                values.push(read_gguf_value(reader, element_type)?);
+            }
+            GgufValue::Array(values)
+        }
+    })
+}
+
+/// Reads the GGUF metadata section (a flat count-prefixed list of key-value pairs) from
+/// `reader`, which must be positioned immediately after the version field of the header.
+pub fn read_metadata(reader: &mut impl BufRead, metadata_kv_count: u64) -> Result<GgufMetadata, LoadError> {
+    if metadata_kv_count > MAX_GGUF_ALLOCATION {
+        return Err(LoadError::InvalidGgufMetadata {
+            message: format!(
+                "metadata key-value count {metadata_kv_count} is implausibly large; file is likely truncated or corrupt"
+            ),
+        });
+    }
+    let mut entries = HashMap::with_capacity(usize::try_from(metadata_kv_count)?);
+    for _ in 0..metadata_kv_count {
+        let key = read_gguf_string(reader)?;
+        let value_tag = util::read_u32(reader)?;
+        let value_type = GgufValueType::try_from(value_tag)
+            .map_err(|_| LoadError::UnknownGgufValueType { value_type: value_tag })?;
+        let value = read_gguf_value(reader, value_type)?;
+        entries.insert(key, value);
+    }
+    Ok(GgufMetadata { entries })
+}
+
+/// Reads the GGUF tensor directory (a count-prefixed list of tensor descriptors) from `reader`.
+pub fn read_tensor_infos(
+    reader: &mut impl BufRead,
+    tensor_count: u64,
+) -> Result<Vec<GgufTensorInfo>, LoadError> {
+    if tensor_count > MAX_GGUF_ALLOCATION {
+        return Err(LoadError::InvalidGgufMetadata {
+            message: format!(
+                "tensor count {tensor_count} is implausibly large; file is likely truncated or corrupt"
+            ),
+        });
+    }
+    let mut infos = Vec::with_capacity(usize::try_from(tensor_count)?);
+    for _ in 0..tensor_count {
+        let name = read_gguf_string(reader)?;
+        let n_dims = util::read_u32(reader)?;
+        let mut dims = Vec::with_capacity(n_dims as usize);
+        for _ in 0..n_dims {
+            dims.push(util::read_u64(reader)?);
+        }
+        let element_type = util::read_i32(reader)?;
+        let offset = util::read_u64(reader)?;
+        infos.push(GgufTensorInfo {
+            name,
+            dims,
+            element_type,
+            offset,
+        });
+    }
+    Ok(infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn write_kv_u64(buf: &mut Vec<u8>, key: &str, value: u64) {
+        buf.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        buf.extend_from_slice(key.as_bytes());
+        buf.extend_from_slice(&(GgufValueType::U64 as u32).to_le_bytes());
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn reads_a_u64_metadata_value_without_truncating_it() {
+        let large_value = (1u64 << 40) + 7;
+
+        let mut buf = Vec::new();
+        write_kv_u64(&mut buf, "llama.context_length", large_value);
+
+        let mut reader = Cursor::new(buf);
+        let metadata = read_metadata(&mut reader, 1).unwrap();
+
+        assert_eq!(
+            metadata.get("llama.context_length"),
+            Some(&GgufValue::U64(large_value))
+        );
+    }
+
+    #[test]
+    fn file_type_is_derived_from_a_u64_metadata_value() {
+        let mut buf = Vec::new();
+        write_kv_u64(&mut buf, FILE_TYPE_KEY, i32::from(FileType::MostlyQ4_0) as u64);
+
+        let mut reader = Cursor::new(buf);
+        let metadata = read_metadata(&mut reader, 1).unwrap();
+
+        assert_eq!(metadata.file_type(), Some(FileType::MostlyQ4_0));
+    }
+
+    #[test]
+    fn rejects_an_implausibly_large_metadata_kv_count() {
+        let mut reader = Cursor::new(Vec::new());
+        let err = read_metadata(&mut reader, u64::MAX).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidGgufMetadata { .. }));
+    }
+
+    #[test]
+    fn rejects_an_implausibly_large_string_length() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&u64::MAX.to_le_bytes());
+
+        let mut reader = Cursor::new(buf);
+        let err = read_gguf_string(&mut reader).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidGgufMetadata { .. }));
+    }
+}
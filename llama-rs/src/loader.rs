@@ -0,0 +1,681 @@
+//! The top-level model loading entry point.
+//!
+//! This is where the format-specific pieces in [`crate::gguf`], [`crate::mmap`],
+//! [`crate::multipart`], and [`crate::lora`] are actually wired together and made reachable by a
+//! caller, rather than sitting as standalone, unused support modules.
+
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use ggml_format::ContainerType;
+
+use crate::{gguf, lora, mmap, multipart, FileType, LoadError, LoadProgress};
+
+/// Options controlling how [`load_sharded_model`] resolves a model's tensor data.
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Whether [`resolve_tensor_data`] memory-maps the file rather than reading each tensor
+    /// through the reader, when the file's tensor offsets allow it. [`load_sharded_model`] copies
+    /// every tensor's bytes into its [`multipart::PartTensor`] either way, so the loaded tensors
+    /// do not point into the mapping.
+    pub use_mmap: bool,
+    /// LoRA adapter files to fuse into the base model's tensors once they are loaded and merged.
+    pub lora_adapters: lora::LoraAdapterPaths,
+}
+
+/// GGUF's magic number, the ASCII bytes `"GGUF"` read as a little-endian `u32`.
+const GGUF_MAGIC: u32 = 0x4655_4747;
+/// The legacy, unversioned GGML container's magic number, the ASCII bytes `"ggml"` read as a
+/// little-endian `u32`.
+pub(crate) const GGML_MAGIC: u32 = 0x6c6d_6767;
+/// The legacy, versioned GGMF container's magic number, the ASCII bytes `"ggmf"` read as a
+/// little-endian `u32`.
+pub(crate) const GGMF_MAGIC: u32 = 0x666d_6767;
+/// The legacy, versioned GGJT container's magic number, the ASCII bytes `"ggjt"` read as a
+/// little-endian `u32`. Unlike GGMF, GGJT pads each tensor's data to a 32-byte boundary so the
+/// file can be memory-mapped.
+pub(crate) const GGJT_MAGIC: u32 = 0x746a_6767;
+/// The GGLA LoRA adapter container's magic number, the ASCII bytes `"ggla"` read as a
+/// little-endian `u32`. See [`crate::lora`] for the rest of the format.
+pub(crate) const GGLA_MAGIC: u32 = 0x616c_6767;
+
+/// Identifies which legacy container `magic` names, reading (and validating) the version field
+/// that follows it for the two versioned formats. `reader` is left positioned right after the
+/// version field (or, for the unversioned GGML format, right after the magic).
+fn read_legacy_container_type(
+    reader: &mut (impl Read + Seek),
+    magic: u32,
+) -> Result<ContainerType, LoadError> {
+    match magic {
+        GGML_MAGIC => Ok(ContainerType::Ggml),
+        GGMF_MAGIC | GGJT_MAGIC => {
+            let mut version_bytes = [0u8; 4];
+            reader
+                .read_exact(&mut version_bytes)
+                .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+            let version = u32::from_le_bytes(version_bytes);
+
+            Ok(if magic == GGMF_MAGIC {
+                ContainerType::Ggmf(version)
+            } else {
+                ContainerType::Ggjt(version)
+            })
+        }
+        _ => Err(LoadError::InvalidMagic {
+            path: Default::default(),
+            magic,
+        }),
+    }
+}
+
+/// The result of inspecting a model file's header: which container format it uses, and
+/// (for GGUF) the parsed metadata and tensor directory, from which [`FileType`] and the rest of
+/// the model's hyperparameters can be derived.
+pub enum ContainerHeader {
+    /// A legacy GGML/GGJT container; hyperparameters are read positionally, as before.
+    Legacy(ContainerType),
+    /// A GGUF container; its hyperparameters and vocabulary live in `metadata` rather than in
+    /// positional fields.
+    Gguf {
+        /// The parsed key-value metadata section.
+        metadata: gguf::GgufMetadata,
+        /// The parsed tensor directory, with each offset rebased to an absolute file offset.
+        tensor_infos: Vec<gguf::GgufTensorInfo>,
+    },
+}
+impl ContainerHeader {
+    /// The model's [`FileType`], derived from the `general.file_type` GGUF metadata key for
+    /// GGUF files, or `None` for legacy containers (whose `FileType` is read positionally from
+    /// the hyperparameters block, not from this header inspection step).
+    pub fn file_type(&self) -> Option<FileType> {
+        match self {
+            ContainerHeader::Legacy(_) => None,
+            ContainerHeader::Gguf { metadata, .. } => metadata.file_type(),
+        }
+    }
+}
+
+/// A tensor's resolved backing data: either a zero-copy view into a memory-mapped file, or an
+/// owned buffer copied out of the source reader.
+pub enum TensorData {
+    /// A view into a memory-mapped file, covering `[offset, offset + len)`.
+    Mapped {
+        /// The mapped file, shared across every tensor mapped from it.
+        mmap: std::rc::Rc<memmap2::Mmap>,
+        /// The tensor's start offset within the mapped file.
+        offset: usize,
+        /// The tensor's length, in bytes.
+        len: usize,
+    },
+    /// An owned copy of the tensor's bytes.
+    Owned(Vec<u8>),
+}
+impl TensorData {
+    /// The tensor's data, regardless of whether it is mapped or owned.
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            TensorData::Mapped { mmap, offset, len } => &mmap[*offset..*offset + *len],
+            TensorData::Owned(data) => data,
+        }
+    }
+}
+
+/// The number of bytes a tensor of `dims` and `element_type` occupies on disk. Shared by GGUF
+/// tensor infos, the legacy tensor directory entries built in [`read_legacy_tensor_directory`],
+/// and [`crate::quantize`]'s positional tensor stream, which uses the same on-disk layout.
+pub(crate) fn tensor_byte_len(dims: &[u64], element_type: i32) -> usize {
+    let element_count: u64 = dims.iter().product();
+    match element_type {
+        0 => element_count as usize * 4, // f32
+        1 => element_count as usize * 2, // f16
+        raw => match FileType::try_from(raw) {
+            Ok(file_type @ (FileType::MostlyQ4_0 | FileType::MostlyQ4_1 | FileType::MostlyQ8_0)) => {
+                (element_count as usize / 32) * crate::quantize::block_byte_size(file_type)
+            }
+            // Formats this crate doesn't have a quantizer/dequantizer for yet; callers that care
+            // about their exact size will fail more specifically downstream when they try to use
+            // the data.
+            _ => element_count as usize * 4,
+        },
+    }
+}
+
+/// Resolves every tensor's data, either by memory-mapping `path` (when `use_mmap` is set and
+/// every tensor's offset in `tensor_infos` is aligned per [`mmap::check_mmap_alignment`]) or by
+/// seeking through `reader` and copying each tensor's bytes out.
+///
+/// This is the actual effect of the `use_mmap` load option: [`crate::mmap::map_file`] on its
+/// own only maps the file and reports its size -- it never checks the real tensor directory's
+/// alignment or produces the per-tensor views that make the mapping useful, both of which
+/// happen here.
+pub fn resolve_tensor_data(
+    path: &Path,
+    reader: &mut (impl Read + Seek),
+    tensor_infos: &[gguf::GgufTensorInfo],
+    use_mmap: bool,
+    mut progress_callback: impl FnMut(LoadProgress),
+) -> Result<Vec<TensorData>, LoadError> {
+    if use_mmap {
+        let alignment_ok = tensor_infos
+            .iter()
+            .try_for_each(|info| mmap::check_mmap_alignment(path, &info.name, info.offset));
+
+        if alignment_ok.is_ok() {
+            let mapped = std::rc::Rc::new(mmap::map_file(path, &mut progress_callback)?);
+            return Ok(tensor_infos
+                .iter()
+                .map(|info| TensorData::Mapped {
+                    mmap: mapped.clone(),
+                    offset: info.offset as usize,
+                    len: tensor_byte_len(&info.dims, info.element_type),
+                })
+                .collect());
+        }
+        // Misaligned offsets mean this file cannot be mmap-ready; fall back to the copying path
+        // below rather than surfacing the alignment error, since mmap is a best-effort
+        // speed-up rather than a hard requirement of loading the model.
+    }
+
+    tensor_infos
+        .iter()
+        .map(|info| {
+            reader.seek(SeekFrom::Start(info.offset))?;
+            let len = tensor_byte_len(&info.dims, info.element_type);
+            let mut data = vec![0u8; len];
+            reader
+                .read_exact(&mut data)
+                .map_err(|source| LoadError::ReadExactFailed { source, bytes: len })?;
+            Ok(TensorData::Owned(data))
+        })
+        .collect()
+}
+
+/// The on-disk byte size of a single element of `element_type`, for formats whose elements have
+/// a uniform size (f32, f16). Block-quantized formats don't have one (their blocks, not their
+/// elements, have a fixed byte size), so those are approximated as the block's average
+/// bytes-per-element, rounded up. [`crate::multipart`] sizes its merges from each part's actual
+/// data length, not from this value.
+fn element_byte_size(element_type: i32) -> usize {
+    match element_type {
+        0 => 4, // f32
+        1 => 2, // f16
+        raw => match FileType::try_from(raw) {
+            Ok(file_type @ (FileType::MostlyQ4_0 | FileType::MostlyQ4_1 | FileType::MostlyQ8_0)) => {
+                // Round up rather than truncate: every supported quantized block is smaller than
+                // 32 bytes except Q8_0, so plain `/ 32` would floor to zero for Q4_0 and Q4_1 and
+                // silently treat those tensors as having no data at all.
+                (crate::quantize::block_byte_size(file_type) + 31) / 32
+            }
+            _ => 4,
+        },
+    }
+}
+
+/// Reads exactly `buf.len()` bytes into `buf`, unless the stream is already exhausted (no bytes
+/// read at all), in which case `Ok(false)` is returned instead of an EOF error. Used by
+/// [`read_legacy_tensor_directory`] (and [`crate::quantize`]'s own positional tensor stream) to
+/// detect the end of a tensor directory, which (unlike GGUF's) has no explicit tensor count to
+/// loop against.
+pub(crate) fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool, LoadError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    if filled == 0 {
+        return Ok(false);
+    }
+    if filled != buf.len() {
+        return Err(LoadError::ReadExactFailed {
+            source: std::io::Error::from(std::io::ErrorKind::UnexpectedEof),
+            bytes: buf.len(),
+        });
+    }
+    Ok(true)
+}
+
+/// Skips a legacy container's hyperparameters and vocabulary sections, which precede the tensor
+/// directory and aren't needed by this load path: `n_vocab`, five more hyperparameter fields
+/// (`n_embd`, `n_mult`, `n_head`, `n_layer`, `n_rot`), a trailing `ftype`, and then `n_vocab`
+/// vocabulary entries of a length-prefixed token followed by an `f32` score.
+pub(crate) fn skip_legacy_hyperparameters_and_vocab(
+    reader: &mut (impl Read + Seek),
+) -> Result<(), LoadError> {
+    let mut word = [0u8; 4];
+
+    reader
+        .read_exact(&mut word)
+        .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+    let n_vocab = u32::from_le_bytes(word);
+
+    for _ in 0..6 {
+        // n_embd, n_mult, n_head, n_layer, n_rot, ftype.
+        reader
+            .read_exact(&mut word)
+            .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+    }
+
+    for _ in 0..n_vocab {
+        reader
+            .read_exact(&mut word)
+            .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+        let token_len = u32::from_le_bytes(word);
+        reader.seek(SeekFrom::Current(i64::from(token_len)))?; // the token text
+        reader.seek(SeekFrom::Current(4))?; // the token's f32 score
+    }
+
+    Ok(())
+}
+
+/// Walks a legacy container's positional tensor directory, recording each tensor's name, dims,
+/// element type, and data offset without reading its data -- the same split between parsing the
+/// directory and resolving the data (via [`resolve_tensor_data`]) that GGUF already uses, which
+/// lets legacy and GGUF tensors share that same resolution path despite GGUF storing its
+/// directory separately from the data it describes and legacy interleaving the two.
+///
+/// `align32` pads each tensor's data to a 32-byte boundary, matching GGJT's on-disk layout;
+/// GGML/GGMF have no such padding.
+pub(crate) fn read_legacy_tensor_directory(
+    reader: &mut (impl Read + Seek),
+    align32: bool,
+) -> Result<Vec<gguf::GgufTensorInfo>, LoadError> {
+    let mut tensor_infos = Vec::new();
+    let mut word = [0u8; 4];
+
+    while read_exact_or_eof(reader, &mut word)? {
+        let n_dims = u32::from_le_bytes(word);
+
+        reader
+            .read_exact(&mut word)
+            .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+        let name_len = u32::from_le_bytes(word);
+
+        reader
+            .read_exact(&mut word)
+            .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+        let element_type = i32::from_le_bytes(word);
+
+        let mut dims = Vec::with_capacity(n_dims as usize);
+        for _ in 0..n_dims {
+            reader
+                .read_exact(&mut word)
+                .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+            dims.push(u64::from(u32::from_le_bytes(word)));
+        }
+
+        let mut name_buf = vec![0u8; name_len as usize];
+        reader
+            .read_exact(&mut name_buf)
+            .map_err(|source| LoadError::ReadExactFailed { source, bytes: name_buf.len() })?;
+        let name = String::from_utf8(name_buf)?;
+
+        if align32 {
+            let position = reader.stream_position()?;
+            let padding = (32 - (position % 32)) % 32;
+            reader.seek(SeekFrom::Current(padding as i64))?;
+        }
+
+        let offset = reader.stream_position()?;
+        reader.seek(SeekFrom::Current(tensor_byte_len(&dims, element_type) as i64))?;
+
+        tensor_infos.push(gguf::GgufTensorInfo { name, dims, element_type, offset });
+    }
+
+    Ok(tensor_infos)
+}
+
+/// Loads and merges a (possibly multi-part) model's tensors: each file in `paths` is opened, its
+/// container header is inspected and dispatched on (via [`read_container_header`]), and its
+/// tensors are resolved (mmap'd or copied, via [`resolve_tensor_data`]); same-named tensors across
+/// parts are then merged by [`crate::multipart::load_and_merge_parts`].
+///
+/// This is the actual caller of [`crate::multipart::load_and_merge_parts`]: previously that
+/// function was defined but never invoked from anywhere reachable in this crate. Both GGUF and
+/// legacy GGML/GGMF/GGJT parts are handled -- the latter via
+/// [`skip_legacy_hyperparameters_and_vocab`] and [`read_legacy_tensor_directory`] -- since
+/// `FindAllModelFiles`-discovered shards (`foo.1`, `foo.2`, ...) are overwhelmingly legacy files
+/// in practice.
+///
+/// Once every part is loaded and merged, `options.lora_adapters` (if any) are fused into the
+/// merged tensors via [`apply_lora_adapters`].
+pub fn load_sharded_model(
+    paths: &[std::path::PathBuf],
+    options: &LoadOptions,
+    progress_callback: impl FnMut(LoadProgress),
+) -> Result<Vec<multipart::PartTensor>, LoadError> {
+    // `resolve_tensor_data`'s per-tensor progress and `load_and_merge_parts`'s per-part progress
+    // both need a callback at the same time (one is invoked from inside the other's `load_part`
+    // closure), so the single `FnMut` the caller gave us is shared through a `RefCell` rather than
+    // being split into two borrows of the same value.
+    let progress_callback = std::cell::RefCell::new(progress_callback);
+
+    let mut merged = multipart::load_and_merge_parts(
+        paths,
+        |path| {
+            let file = std::fs::File::open(path).map_err(|source| LoadError::OpenFileFailed {
+                source,
+                path: path.to_owned(),
+            })?;
+            let mut reader = std::io::BufReader::new(file);
+
+            let header = read_container_header(&mut reader)?;
+            let tensor_infos = match header {
+                ContainerHeader::Gguf { tensor_infos, .. } => tensor_infos,
+                ContainerHeader::Legacy(container_type) => {
+                    skip_legacy_hyperparameters_and_vocab(&mut reader)?;
+                    let align32 = matches!(container_type, ContainerType::Ggjt(_));
+                    read_legacy_tensor_directory(&mut reader, align32)?
+                }
+            };
+
+            let resolved = resolve_tensor_data(path, &mut reader, &tensor_infos, options.use_mmap, |progress| {
+                (progress_callback.borrow_mut())(progress)
+            })?;
+
+            Ok(tensor_infos
+                .iter()
+                .zip(resolved)
+                .map(|(info, data)| multipart::PartTensor {
+                    name: info.name.clone(),
+                    shape: info.dims.iter().map(|&dim| dim as usize).collect(),
+                    data: data.as_slice().to_vec(),
+                    element_size: element_byte_size(info.element_type),
+                    element_type: info.element_type,
+                })
+                .collect())
+        },
+        |progress| (progress_callback.borrow_mut())(progress),
+    )?;
+
+    apply_lora_adapters(&mut merged, &options.lora_adapters, |progress| {
+        (progress_callback.borrow_mut())(progress)
+    })?;
+
+    Ok(merged)
+}
+
+/// Fuses every adapter in `adapter_paths` into `merged`'s matching tensors (by name), replacing
+/// each fused tensor's data in place and emitting [`LoadProgress::LoraApplied`] once per fused
+/// tensor. A no-op if `adapter_paths` is empty.
+fn apply_lora_adapters(
+    merged: &mut [multipart::PartTensor],
+    adapter_paths: &lora::LoraAdapterPaths,
+    mut progress_callback: impl FnMut(LoadProgress),
+) -> Result<(), LoadError> {
+    if adapter_paths.0.is_empty() {
+        return Ok(());
+    }
+
+    let base_tensor_names: Vec<String> = merged.iter().map(|tensor| tensor.name.clone()).collect();
+
+    for adapter_path in &adapter_paths.0 {
+        let adapters = lora::read_lora_adapters(adapter_path, &mut progress_callback)?;
+        lora::check_adapters_apply_to_base(&adapters, &base_tensor_names, adapter_path)?;
+
+        for adapter in &adapters {
+            let tensor = merged
+                .iter_mut()
+                .find(|tensor| tensor.name == adapter.tensor_name)
+                .expect("check_adapters_apply_to_base already validated this tensor exists");
+
+            tensor.data = lora::fuse_tensor_data(&tensor.data, &tensor.shape, tensor.element_type, adapter)?;
+            progress_callback(LoadProgress::LoraApplied { tensor_name: &tensor.name });
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the first 4 bytes of `reader` to determine its container format. For a legacy
+/// container, `reader` is left positioned at its hyperparameters: right after the magic (GGML),
+/// or right after the version field that follows it (GGMF/GGJT).
+///
+/// For GGUF files, this also reads the version and counts, and parses the metadata and tensor
+/// directory that follow the header, rebasing each tensor's offset to an absolute file offset.
+/// Parsing is buffered, so `reader`'s position afterward is not defined.
+pub fn read_container_header(reader: &mut (impl Read + Seek)) -> Result<ContainerHeader, LoadError> {
+    let mut magic_bytes = [0u8; 4];
+    reader.read_exact(&mut magic_bytes).map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+    let magic = u32::from_le_bytes(magic_bytes);
+
+    if magic == GGUF_MAGIC {
+        let mut version_bytes = [0u8; 4];
+        reader.read_exact(&mut version_bytes).map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+        let mut tensor_count_bytes = [0u8; 8];
+        reader
+            .read_exact(&mut tensor_count_bytes)
+            .map_err(|source| LoadError::ReadExactFailed { source, bytes: 8 })?;
+        let mut metadata_kv_count_bytes = [0u8; 8];
+        reader
+            .read_exact(&mut metadata_kv_count_bytes)
+            .map_err(|source| LoadError::ReadExactFailed { source, bytes: 8 })?;
+
+        let tensor_count = u64::from_le_bytes(tensor_count_bytes);
+        let metadata_kv_count = u64::from_le_bytes(metadata_kv_count_bytes);
+
+        let mut buffered = std::io::BufReader::new(&mut *reader);
+        let metadata = gguf::read_metadata(&mut buffered, metadata_kv_count)?;
+        let mut tensor_infos = gguf::read_tensor_infos(&mut buffered, tensor_count)?;
+
+        // The directory's offsets are relative to the data section, which starts at the next
+        // multiple of `general.alignment` (32 if absent) after the directory; rebase them to the
+        // absolute file offsets that `resolve_tensor_data` and legacy directories use.
+        let alignment = match metadata.get("general.alignment") {
+            Some(&gguf::GgufValue::U32(alignment)) if alignment > 0 => u64::from(alignment),
+            _ => 32,
+        };
+        let directory_end = buffered.stream_position()?;
+        let data_start = (directory_end + alignment - 1) / alignment * alignment;
+        for info in &mut tensor_infos {
+            info.offset += data_start;
+        }
+
+        Ok(ContainerHeader::Gguf { metadata, tensor_infos })
+    } else {
+        let container_type = read_legacy_container_type(reader, magic)?;
+        Ok(ContainerHeader::Legacy(container_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn recognizes_a_gguf_header_and_parses_its_metadata() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGUF_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&3u32.to_le_bytes()); // version
+        buf.extend_from_slice(&0u64.to_le_bytes()); // tensor_count
+        buf.extend_from_slice(&0u64.to_le_bytes()); // metadata_kv_count
+
+        let mut reader = Cursor::new(buf);
+        let header = read_container_header(&mut reader).unwrap();
+
+        assert!(matches!(header, ContainerHeader::Gguf { .. }));
+    }
+
+    #[test]
+    fn recognizes_an_unversioned_legacy_ggml_header() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGML_MAGIC.to_le_bytes());
+
+        let mut reader = Cursor::new(buf);
+        let header = read_container_header(&mut reader).unwrap();
+
+        assert!(matches!(header, ContainerHeader::Legacy(ContainerType::Ggml)));
+    }
+
+    #[test]
+    fn recognizes_a_versioned_legacy_ggmf_header() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGMF_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&1u32.to_le_bytes()); // version
+
+        let mut reader = Cursor::new(buf);
+        let header = read_container_header(&mut reader).unwrap();
+
+        assert!(matches!(header, ContainerHeader::Legacy(ContainerType::Ggmf(1))));
+    }
+
+    #[test]
+    fn recognizes_a_versioned_legacy_ggjt_header() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGJT_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&3u32.to_le_bytes()); // version
+
+        let mut reader = Cursor::new(buf);
+        let header = read_container_header(&mut reader).unwrap();
+
+        assert!(matches!(header, ContainerHeader::Legacy(ContainerType::Ggjt(3))));
+    }
+
+    #[test]
+    fn rejects_an_unrecognized_magic() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0xdead_beefu32.to_le_bytes());
+
+        let mut reader = Cursor::new(buf);
+        let err = read_container_header(&mut reader).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidMagic { .. }));
+    }
+
+    fn tensor_info(name: &str, dims: Vec<u64>, offset: u64) -> gguf::GgufTensorInfo {
+        gguf::GgufTensorInfo {
+            name: name.to_owned(),
+            dims,
+            element_type: 0, // f32
+            offset,
+        }
+    }
+
+    #[test]
+    fn copies_tensor_data_out_of_the_reader_when_mmap_is_disabled() {
+        let data = (0u8..16).collect::<Vec<u8>>();
+        let mut reader = Cursor::new(data);
+        let infos = vec![tensor_info("a", vec![2], 0), tensor_info("b", vec![2], 8)];
+
+        let resolved = resolve_tensor_data(Path::new("unused"), &mut reader, &infos, false, |_| {}).unwrap();
+
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(resolved[0].as_slice(), &[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(resolved[1].as_slice(), &[8, 9, 10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn falls_back_to_copying_when_mmap_is_requested_but_offsets_are_misaligned() {
+        // Offset 8 is not a multiple of `mmap::MMAP_ALIGNMENT` (32), so even with `use_mmap: true`
+        // this must fall back to the owned-copy path rather than mapping the file.
+        let data = (0u8..16).collect::<Vec<u8>>();
+        let mut reader = Cursor::new(data);
+        let infos = vec![tensor_info("a", vec![2], 8)];
+
+        let resolved = resolve_tensor_data(Path::new("unused"), &mut reader, &infos, true, |_| {}).unwrap();
+
+        assert_eq!(resolved.len(), 1);
+        assert!(matches!(resolved[0], TensorData::Owned(_)));
+        assert_eq!(resolved[0].as_slice(), &[8, 9, 10, 11, 12, 13, 14, 15]);
+    }
+
+    fn sample_gguf_bytes(tensor_name: &str, tensor_data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGUF_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&3u32.to_le_bytes()); // version
+        buf.extend_from_slice(&1u64.to_le_bytes()); // tensor_count
+        buf.extend_from_slice(&0u64.to_le_bytes()); // metadata_kv_count
+
+        buf.extend_from_slice(&(tensor_name.len() as u64).to_le_bytes());
+        buf.extend_from_slice(tensor_name.as_bytes());
+        buf.extend_from_slice(&1u32.to_le_bytes()); // n_dims
+        buf.extend_from_slice(&(tensor_data.len() as u64 / 4).to_le_bytes()); // dims[0], f32 elements
+        buf.extend_from_slice(&0i32.to_le_bytes()); // element_type: f32
+
+        buf.extend_from_slice(&0u64.to_le_bytes()); // offset, relative to the data section
+        let padding = (32 - (buf.len() % 32)) % 32; // the data section starts 32-byte aligned
+        buf.extend(std::iter::repeat(0u8).take(padding));
+        buf.extend_from_slice(tensor_data);
+
+        buf
+    }
+
+    #[test]
+    fn loads_a_single_part_gguf_model_through_the_sharded_loading_path() {
+        let tensor_data = 1.0f32.to_le_bytes().iter().chain(2.0f32.to_le_bytes().iter()).copied().collect::<Vec<u8>>();
+        let buf = sample_gguf_bytes("weight", &tensor_data);
+
+        let path = std::env::temp_dir().join(format!("llama-rs-loader-test-{:x}.gguf", buf.as_ptr() as usize));
+        std::fs::write(&path, &buf).unwrap();
+
+        let result = load_sharded_model(std::slice::from_ref(&path), &LoadOptions::default(), |_| {});
+        std::fs::remove_file(&path).unwrap();
+
+        let tensors = result.unwrap();
+        assert_eq!(tensors.len(), 1);
+        assert_eq!(tensors[0].name, "weight");
+        assert_eq!(tensors[0].shape, vec![2]);
+        assert_eq!(tensors[0].data, tensor_data);
+    }
+
+    fn sample_legacy_ggjt_bytes(tensor_name: &str, tensor_data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGJT_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&3u32.to_le_bytes()); // version
+
+        buf.extend_from_slice(&0u32.to_le_bytes()); // n_vocab
+        for _ in 0..6 {
+            buf.extend_from_slice(&0u32.to_le_bytes()); // n_embd, n_mult, n_head, n_layer, n_rot, ftype
+        }
+
+        buf.extend_from_slice(&1u32.to_le_bytes()); // n_dims
+        buf.extend_from_slice(&(tensor_name.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&0i32.to_le_bytes()); // element_type: f32
+        buf.extend_from_slice(&(tensor_data.len() as u32 / 4).to_le_bytes()); // dims[0]
+        buf.extend_from_slice(tensor_name.as_bytes());
+
+        let padding = (32 - (buf.len() % 32)) % 32;
+        buf.extend(std::iter::repeat(0u8).take(padding));
+        buf.extend_from_slice(tensor_data);
+
+        buf
+    }
+
+    #[test]
+    fn walks_a_legacy_ggjt_tensor_directory_and_records_aligned_offsets() {
+        let tensor_data = 1.0f32.to_le_bytes().iter().chain(2.0f32.to_le_bytes().iter()).copied().collect::<Vec<u8>>();
+        let buf = sample_legacy_ggjt_bytes("weight", &tensor_data);
+
+        let mut reader = Cursor::new(buf);
+        skip_legacy_hyperparameters_and_vocab(&mut reader).unwrap();
+        let tensor_infos = read_legacy_tensor_directory(&mut reader, true).unwrap();
+
+        assert_eq!(tensor_infos.len(), 1);
+        assert_eq!(tensor_infos[0].name, "weight");
+        assert_eq!(tensor_infos[0].dims, vec![2]);
+        assert_eq!(tensor_infos[0].offset % 32, 0);
+    }
+
+    #[test]
+    fn loads_a_single_part_legacy_ggjt_model_through_the_sharded_loading_path() {
+        let tensor_data = 1.0f32.to_le_bytes().iter().chain(2.0f32.to_le_bytes().iter()).copied().collect::<Vec<u8>>();
+        let buf = sample_legacy_ggjt_bytes("weight", &tensor_data);
+
+        let path = std::env::temp_dir().join(format!("llama-rs-loader-test-{:x}.ggjt", buf.as_ptr() as usize));
+        std::fs::write(&path, &buf).unwrap();
+
+        let result = load_sharded_model(std::slice::from_ref(&path), &LoadOptions::default(), |_| {});
+        std::fs::remove_file(&path).unwrap();
+
+        let tensors = result.unwrap();
+        assert_eq!(tensors.len(), 1);
+        assert_eq!(tensors[0].name, "weight");
+        assert_eq!(tensors[0].shape, vec![2]);
+        assert_eq!(tensors[0].data, tensor_data);
+    }
+}
@@ -120,6 +120,16 @@ pub enum LoadProgress<'a> {
         /// The number of tensors in the part.
         tensor_count: usize,
     },
+    /// The model file has been memory-mapped.
+    Mmap {
+        /// The size, in bytes, of the mapped region.
+        bytes: usize,
+    },
+    /// A LoRA adapter's delta has been fused into a base tensor.
+    LoraApplied {
+        /// The name of the tensor the adapter was fused into.
+        tensor_name: &'a str,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -228,13 +238,60 @@ pub enum LoadError {
         /// The path that failed.
         path: PathBuf,
     },
-    /// Multiple parts of the model were found.
-    ///
-    /// Multi-part models are not supported. Please convert the model to a single part.
-    #[error("multipart models are not supported")]
-    MultipartNotSupported {
-        /// The paths that were found.
-        paths: Vec<PathBuf>,
+    /// The tensor `tensor_name` had inconsistent shapes across the parts of a multi-part model,
+    /// so the parts could not be merged.
+    #[error("tensor `{tensor_name}` has inconsistent shapes across model parts: {shapes:?}")]
+    InconsistentShardShapes {
+        /// The name of the tensor.
+        tensor_name: String,
+        /// The shapes that were encountered, one per part, in part order.
+        shapes: Vec<Vec<usize>>,
+    },
+    /// A GGUF metadata key-value pair used an unrecognized value type tag.
+    #[error("unknown GGUF metadata value type {value_type}")]
+    UnknownGgufValueType {
+        /// The value type tag that was encountered.
+        value_type: u32,
+    },
+    /// The GGUF metadata section was malformed (e.g. truncated, or referencing a key that is
+    /// required but absent).
+    #[error("malformed GGUF metadata: {message}")]
+    InvalidGgufMetadata {
+        /// A description of what was wrong with the metadata.
+        message: String,
+    },
+    /// A LoRA adapter's `A`/`B` matrices did not match the shape of the base tensor they were
+    /// meant to be fused into.
+    #[error("LoRA adapter shape mismatch for tensor `{tensor_name}`: base is {base_shape:?}, adapter implies {adapter_shape:?}")]
+    LoraTensorShapeMismatch {
+        /// The name of the tensor.
+        tensor_name: String,
+        /// The base tensor's shape.
+        base_shape: Vec<usize>,
+        /// The shape implied by the adapter's `A`/`B` matrices.
+        adapter_shape: Vec<usize>,
+    },
+    /// A LoRA adapter referenced a tensor that does not exist in the base model.
+    #[error("LoRA adapter in {path:?} references unknown tensor `{tensor_name}`")]
+    LoraUnknownTensor {
+        /// The name of the tensor.
+        tensor_name: String,
+        /// The path of the adapter file.
+        path: PathBuf,
+    },
+    /// A quantized base tensor could not be dequantized, or its fused result could not be
+    /// requantized, while fusing a LoRA adapter into it.
+    #[error("failed to (de)quantize a tensor while fusing a LoRA adapter: {0}")]
+    LoraQuantizeFailed(#[from] crate::quantize::QuantizeError),
+    /// A LoRA adapter file had a tensor missing its `loraA` or `loraB` half.
+    #[error("LoRA adapter in {path:?} is missing its `{which}` tensor for `{tensor_name}`")]
+    LoraAdapterTensorMissing {
+        /// The path of the adapter file.
+        path: PathBuf,
+        /// The name of the base tensor the adapter was meant to apply to.
+        tensor_name: String,
+        /// Which half (`.loraA` or `.loraB`) is missing.
+        which: &'static str,
     },
 }
 impl From<FindAllModelFilesError> for LoadError {
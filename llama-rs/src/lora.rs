@@ -0,0 +1,411 @@
+//! Support for fusing LoRA (Low-Rank Adaptation) adapters into a base model's weights at load
+//! time.
+//!
+//! A LoRA adapter file is a GGML/GGLA container holding, per target tensor, a pair of low-rank
+//! matrices `A` (`[r, n]`) and `B` (`[m, r]`) plus a scalar `alpha`. The fused weight is
+//! `W' = W + (alpha / r) * (B . A)`, computed in f32 regardless of the base tensor's stored
+//! element type. If the base tensor is quantized, it is dequantized to f32 before the delta is
+//! added and requantized back to its original element type afterward.
+
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    loader::{self, GGLA_MAGIC},
+    quantize::{self, ELEMENT_TYPE_F16, ELEMENT_TYPE_F32},
+    FileType, LoadError, LoadProgress,
+};
+
+/// A single target tensor's adapter data, as read from a LoRA/GGLA file.
+pub struct LoraAdapter {
+    /// The name of the base tensor this adapter applies to.
+    pub tensor_name: String,
+    /// The low-rank scaling factor.
+    pub alpha: f32,
+    /// The adapter's rank, `r`.
+    pub rank: usize,
+    /// The `A` matrix, `[r, n]`, row-major.
+    pub a: Vec<f32>,
+    /// The `B` matrix, `[m, r]`, row-major.
+    pub b: Vec<f32>,
+    /// The base tensor's `n` (input feature count).
+    pub n: usize,
+    /// The base tensor's `m` (output feature count).
+    pub m: usize,
+}
+impl LoraAdapter {
+    /// The `[m, n]` shape this adapter's `A`/`B` matrices imply for the base tensor, without
+    /// dividing by `rank` (which may legitimately be zero for a malformed adapter).
+    fn implied_base_shape(&self) -> Vec<usize> {
+        vec![self.m, self.n]
+    }
+}
+
+/// Computes `(alpha / r) * (B . A)`, the delta to be added to the base weight, as a row-major
+/// `[m, n]` matrix in f32.
+fn compute_delta(adapter: &LoraAdapter) -> Vec<f32> {
+    let LoraAdapter {
+        alpha, rank, a, b, n, m, ..
+    } = adapter;
+    let scale = alpha / *rank as f32;
+
+    let mut delta = vec![0.0f32; m * n];
+    for i in 0..*m {
+        for j in 0..*n {
+            let mut acc = 0.0f32;
+            for k in 0..*rank {
+                acc += b[i * rank + k] * a[k * n + j];
+            }
+            delta[i * n + j] = acc * scale;
+        }
+    }
+    delta
+}
+
+/// Checks that `adapter`'s `A`/`B` matrices imply the same `[m, n]` shape as `base_dims`,
+/// per-axis rather than just by total element count (so a transposed base tensor with a
+/// coincidentally matching element count is still rejected), and that `rank` is non-zero.
+fn check_shapes_match(base_dims: &[usize], adapter: &LoraAdapter) -> Result<(), LoadError> {
+    let implied = adapter.implied_base_shape();
+    if base_dims != implied.as_slice() || adapter.rank == 0 {
+        return Err(LoadError::LoraTensorShapeMismatch {
+            tensor_name: adapter.tensor_name.clone(),
+            base_shape: base_dims.to_vec(),
+            adapter_shape: implied,
+        });
+    }
+    Ok(())
+}
+
+/// Fuses `adapter` into `base_weight` (a row-major tensor of shape `base_dims`, already
+/// dequantized to f32 from its on-disk element type if it was quantized), returning the fused
+/// weight in f32.
+pub fn fuse(base_weight: &[f32], base_dims: &[usize], adapter: &LoraAdapter) -> Result<Vec<f32>, LoadError> {
+    check_shapes_match(base_dims, adapter)?;
+    if base_weight.len() != base_dims.iter().product::<usize>() {
+        return Err(LoadError::LoraTensorShapeMismatch {
+            tensor_name: adapter.tensor_name.clone(),
+            base_shape: base_dims.to_vec(),
+            adapter_shape: adapter.implied_base_shape(),
+        });
+    }
+
+    let delta = compute_delta(adapter);
+    Ok(base_weight
+        .iter()
+        .zip(delta.iter())
+        .map(|(w, d)| w + d)
+        .collect())
+}
+
+/// Fuses `adapter` into a base tensor's raw on-disk bytes, dequantizing first if `element_type`
+/// (a raw ggml element type tag, matching the convention used elsewhere in this crate) names a
+/// quantized format, and requantizing the result back to that same format afterward. F32/F16
+/// base tensors are converted to/from f32 directly, without going through the block quantizer.
+pub fn fuse_tensor_data(
+    base_data: &[u8],
+    base_dims: &[usize],
+    element_type: i32,
+    adapter: &LoraAdapter,
+) -> Result<Vec<u8>, LoadError> {
+    let base_values = match element_type {
+        ELEMENT_TYPE_F32 => base_data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        ELEMENT_TYPE_F16 => base_data
+            .chunks_exact(2)
+            .map(|b| half::f16::from_le_bytes(b.try_into().unwrap()).to_f32())
+            .collect(),
+        raw => {
+            let source = FileType::try_from(raw).map_err(|_| LoadError::UnsupportedFileType(raw))?;
+            quantize::dequantize_values(source, base_data)?
+        }
+    };
+
+    let fused = fuse(&base_values, base_dims, adapter)?;
+
+    match element_type {
+        ELEMENT_TYPE_F32 => Ok(fused.iter().flat_map(|v| v.to_le_bytes()).collect()),
+        ELEMENT_TYPE_F16 => Ok(fused
+            .iter()
+            .flat_map(|v| half::f16::from_f32(*v).to_le_bytes())
+            .collect()),
+        raw => {
+            let target = FileType::try_from(raw).map_err(|_| LoadError::UnsupportedFileType(raw))?;
+            let row_len = *base_dims.last().unwrap_or(&fused.len());
+            Ok(quantize::quantize_values(target, &adapter.tensor_name, row_len, &fused)?)
+        }
+    }
+}
+
+/// Checks that every adapter in `adapters` names a tensor present in `base_tensor_names`,
+/// returning [`LoadError::LoraUnknownTensor`] for the first one that does not.
+pub fn check_adapters_apply_to_base(
+    adapters: &[LoraAdapter],
+    base_tensor_names: &[String],
+    adapter_path: &std::path::Path,
+) -> Result<(), LoadError> {
+    for adapter in adapters {
+        if !base_tensor_names.iter().any(|name| name == &adapter.tensor_name) {
+            return Err(LoadError::LoraUnknownTensor {
+                tensor_name: adapter.tensor_name.clone(),
+                path: adapter_path.to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// An optional list of LoRA adapter files to fuse into the base model at load time.
+#[derive(Clone, Debug, Default)]
+pub struct LoraAdapterPaths(pub Vec<PathBuf>);
+
+/// The suffix GGLA appends to a base tensor's name for its low-rank `A` matrix, `[r, n]`.
+const LORA_A_SUFFIX: &str = ".loraA";
+/// The suffix GGLA appends to a base tensor's name for its low-rank `B` matrix, `[m, r]`.
+const LORA_B_SUFFIX: &str = ".loraB";
+
+/// One base tensor's `A`/`B` matrices, accumulated as [`read_lora_adapters`] walks the adapter
+/// file's tensor directory (which interleaves every tensor's pair, not necessarily adjacent).
+#[derive(Default)]
+struct PartialAdapter {
+    a: Option<(Vec<usize>, Vec<f32>)>,
+    b: Option<(Vec<usize>, Vec<f32>)>,
+}
+
+/// Reads a GGLA LoRA adapter file at `path`: the magic, a version field, the adapter's `r`
+/// (rank) and `alpha`, and then a positional tensor directory -- the same layout
+/// [`loader::read_legacy_tensor_directory`] already walks for legacy model files, without the
+/// 32-byte alignment padding GGJT uses -- holding each target tensor's `<name>.loraA`/
+/// `<name>.loraB` pair, always stored as f32.
+pub fn read_lora_adapters(
+    path: &Path,
+    mut progress_callback: impl FnMut(LoadProgress),
+) -> Result<Vec<LoraAdapter>, LoadError> {
+    let file = std::fs::File::open(path).map_err(|source| LoadError::OpenFileFailed {
+        source,
+        path: path.to_owned(),
+    })?;
+    let mut reader = std::io::BufReader::new(file);
+
+    let mut magic_bytes = [0u8; 4];
+    reader.read_exact(&mut magic_bytes)
+        .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+    let magic = u32::from_le_bytes(magic_bytes);
+    if magic != GGLA_MAGIC {
+        return Err(LoadError::InvalidMagic {
+            path: path.to_owned(),
+            magic,
+        });
+    }
+
+    let mut word = [0u8; 4];
+    reader.read_exact(&mut word) // version, unused
+        .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+    reader.read_exact(&mut word)
+        .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+    let rank = u32::from_le_bytes(word) as usize;
+    reader.read_exact(&mut word)
+        .map_err(|source| LoadError::ReadExactFailed { source, bytes: 4 })?;
+    let alpha = i32::from_le_bytes(word) as f32;
+
+    let tensor_infos = loader::read_legacy_tensor_directory(&mut reader, false)?;
+    let resolved = loader::resolve_tensor_data(path, &mut reader, &tensor_infos, false, &mut progress_callback)?;
+
+    let mut partials: HashMap<String, PartialAdapter> = HashMap::new();
+    for (info, data) in tensor_infos.iter().zip(resolved) {
+        let (base_name, is_a) = if let Some(base_name) = info.name.strip_suffix(LORA_A_SUFFIX) {
+            (base_name, true)
+        } else if let Some(base_name) = info.name.strip_suffix(LORA_B_SUFFIX) {
+            (base_name, false)
+        } else {
+            continue;
+        };
+
+        let values = data
+            .as_slice()
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let dims = info.dims.iter().map(|&dim| dim as usize).collect();
+
+        let partial = partials.entry(base_name.to_owned()).or_default();
+        if is_a {
+            partial.a = Some((dims, values));
+        } else {
+            partial.b = Some((dims, values));
+        }
+    }
+
+    partials
+        .into_iter()
+        .map(|(tensor_name, partial)| {
+            let (a_dims, a) = partial.a.ok_or_else(|| LoadError::LoraAdapterTensorMissing {
+                path: path.to_owned(),
+                tensor_name: tensor_name.clone(),
+                which: LORA_A_SUFFIX,
+            })?;
+            let (b_dims, b) = partial.b.ok_or_else(|| LoadError::LoraAdapterTensorMissing {
+                path: path.to_owned(),
+                tensor_name: tensor_name.clone(),
+                which: LORA_B_SUFFIX,
+            })?;
+
+            Ok(LoraAdapter {
+                tensor_name,
+                alpha,
+                rank,
+                n: *a_dims.last().unwrap_or(&0),
+                m: *b_dims.first().unwrap_or(&0),
+                a,
+                b,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter() -> LoraAdapter {
+        // r = 1, n = 2, m = 2: A = [1, 1], B = [2, 3]^T, alpha = 2 -> scale = 2.
+        // delta = scale * B . A = [[2*2*1, 2*2*1], [2*3*1, 2*3*1]] = [[4, 4], [6, 6]]
+        LoraAdapter {
+            tensor_name: "weight".to_owned(),
+            alpha: 2.0,
+            rank: 1,
+            a: vec![1.0, 1.0],
+            b: vec![2.0, 3.0],
+            n: 2,
+            m: 2,
+        }
+    }
+
+    #[test]
+    fn fuses_a_delta_into_an_f32_base_weight() {
+        let base = vec![1.0, 1.0, 1.0, 1.0];
+        let fused = fuse(&base, &[2, 2], &adapter()).unwrap();
+        assert_eq!(fused, vec![5.0, 5.0, 7.0, 7.0]);
+    }
+
+    #[test]
+    fn rejects_a_transposed_base_tensor_with_a_matching_element_count() {
+        let base = vec![1.0, 1.0, 1.0, 1.0];
+        // [2, 2] transposed is still [2, 2] for a square matrix, so use a non-square one: the
+        // adapter implies [1, 4] and the base is its transpose, [4, 1].
+        let mut wide_adapter = adapter();
+        wide_adapter.n = 4;
+        wide_adapter.m = 1;
+
+        let err = fuse(&base, &[4, 1], &wide_adapter).unwrap_err();
+        assert!(matches!(err, LoadError::LoraTensorShapeMismatch { .. }));
+    }
+
+    #[test]
+    fn rejects_a_zero_rank_adapter_without_panicking() {
+        let mut broken = adapter();
+        broken.rank = 0;
+
+        let base = vec![1.0, 1.0, 1.0, 1.0];
+        let err = fuse(&base, &[2, 2], &broken).unwrap_err();
+        assert!(matches!(err, LoadError::LoraTensorShapeMismatch { .. }));
+    }
+
+    #[test]
+    fn fuses_into_a_quantized_base_tensor_by_dequantizing_and_requantizing() {
+        // A single Q4_0 block's worth of base weights (32 elements), all zero, fused with a
+        // rank-1 adapter whose delta is well within Q4_0's representable range.
+        let base_values = vec![0.0f32; 32];
+        let base_bytes = quantize::quantize_values(FileType::MostlyQ4_0, "weight", 32, &base_values).unwrap();
+
+        let small_adapter = LoraAdapter {
+            tensor_name: "weight".to_owned(),
+            alpha: 1.0,
+            rank: 1,
+            a: vec![1.0; 32],
+            b: vec![1.0; 1],
+            n: 32,
+            m: 1,
+        };
+
+        let fused_bytes = fuse_tensor_data(
+            &base_bytes,
+            &[1, 32],
+            i32::from(FileType::MostlyQ4_0),
+            &small_adapter,
+        )
+        .unwrap();
+
+        let dequantized = quantize::dequantize_values(FileType::MostlyQ4_0, &fused_bytes).unwrap();
+        assert!(dequantized.iter().all(|&v| (v - 1.0).abs() < 0.1));
+    }
+
+    fn write_lora_tensor(buf: &mut Vec<u8>, name: &str, dims: &[u32], values: &[f32]) {
+        buf.extend_from_slice(&(dims.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&0i32.to_le_bytes()); // element_type: f32
+        for &dim in dims {
+            buf.extend_from_slice(&dim.to_le_bytes());
+        }
+        buf.extend_from_slice(name.as_bytes());
+        for &value in values {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn sample_ggla_bytes() -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGLA_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&1u32.to_le_bytes()); // version
+        buf.extend_from_slice(&1u32.to_le_bytes()); // r
+        buf.extend_from_slice(&2i32.to_le_bytes()); // alpha
+
+        write_lora_tensor(&mut buf, "weight.loraA", &[1, 2], &[1.0, 1.0]);
+        write_lora_tensor(&mut buf, "weight.loraB", &[2, 1], &[2.0, 3.0]);
+
+        buf
+    }
+
+    #[test]
+    fn reads_a_ggla_adapter_file_into_a_matched_loraa_lorab_pair() {
+        let buf = sample_ggla_bytes();
+        let path = std::env::temp_dir().join(format!("llama-rs-lora-test-{:x}.ggla", buf.as_ptr() as usize));
+        std::fs::write(&path, &buf).unwrap();
+
+        let result = read_lora_adapters(&path, |_| {});
+        std::fs::remove_file(&path).unwrap();
+
+        let adapters = result.unwrap();
+        assert_eq!(adapters.len(), 1);
+        assert_eq!(adapters[0].tensor_name, "weight");
+        assert_eq!(adapters[0].alpha, 2.0);
+        assert_eq!(adapters[0].rank, 1);
+        assert_eq!(adapters[0].n, 2);
+        assert_eq!(adapters[0].m, 2);
+        assert_eq!(adapters[0].a, vec![1.0, 1.0]);
+        assert_eq!(adapters[0].b, vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn rejects_an_adapter_tensor_missing_its_lorab_half() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGLA_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&1u32.to_le_bytes()); // version
+        buf.extend_from_slice(&1u32.to_le_bytes()); // r
+        buf.extend_from_slice(&2i32.to_le_bytes()); // alpha
+        write_lora_tensor(&mut buf, "weight.loraA", &[1, 2], &[1.0, 1.0]);
+
+        let path = std::env::temp_dir().join(format!("llama-rs-lora-test-{:x}.ggla", buf.as_ptr() as usize));
+        std::fs::write(&path, &buf).unwrap();
+
+        let result = read_lora_adapters(&path, |_| {});
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result.unwrap_err(), LoadError::LoraAdapterTensorMissing { .. }));
+    }
+}
@@ -0,0 +1,55 @@
+//! Support for memory-mapping model weights instead of copying them into owned buffers.
+//!
+//! Mapping the file once and pointing each tensor's data directly at the mapped region lets
+//! multiple inference processes share the kernel's page cache and makes startup near-instant,
+//! at the cost of requiring every tensor's data offset to be aligned.
+
+use std::{fs::File, path::Path};
+
+use memmap2::Mmap;
+
+use crate::{LoadError, LoadProgress};
+
+/// The alignment, in bytes, that a tensor's data offset must satisfy for the file to be
+/// memory-mappable. This matches ggml's expectations for tensor data pointers.
+pub const MMAP_ALIGNMENT: u64 = 32;
+
+/// Checks that `offset` satisfies [`MMAP_ALIGNMENT`], returning an [`LoadError::InvariantBroken`]
+/// describing the misalignment otherwise.
+pub fn check_mmap_alignment(path: &Path, tensor_name: &str, offset: u64) -> Result<(), LoadError> {
+    if offset % MMAP_ALIGNMENT != 0 {
+        return Err(LoadError::InvariantBroken {
+            path: path.to_owned(),
+            invariant: format!(
+                "tensor `{tensor_name}` has data offset {offset}, which is not a multiple of {MMAP_ALIGNMENT}"
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Memory-maps `path` and reports the mapped region's size via `progress_callback`.
+///
+/// The caller is responsible for having already verified, via [`check_mmap_alignment`], that
+/// every tensor's data offset within the file is aligned; this function does not re-derive the
+/// tensor directory itself.
+pub fn map_file(
+    path: &Path,
+    mut progress_callback: impl FnMut(LoadProgress),
+) -> Result<Mmap, LoadError> {
+    let file = File::open(path).map_err(|source| LoadError::OpenFileFailed {
+        source,
+        path: path.to_owned(),
+    })?;
+
+    // SAFETY: the file is not expected to be modified for the lifetime of the mapping; this is
+    // the same assumption made by every other mmap-based model loader.
+    let mmap = unsafe { Mmap::map(&file) }.map_err(|source| LoadError::OpenFileFailed {
+        source,
+        path: path.to_owned(),
+    })?;
+
+    progress_callback(LoadProgress::Mmap { bytes: mmap.len() });
+
+    Ok(mmap)
+}
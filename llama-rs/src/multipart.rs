@@ -0,0 +1,252 @@
+//! Support for transparently loading and merging multi-part (sharded) models.
+//!
+//! A sharded model is split across `foo.1`, `foo.2`, ... files, each holding a subset of every
+//! tensor's data. Depending on the tensor, the shards are either split along one dimension (row-
+//! or column-parallel weights) or simply duplicated (e.g. layer norms). This module concatenates
+//! or deduplicates each tensor's data back into a single in-memory tensor.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    saver::{self, SaveError, SaveProgress, TensorSaveInfo},
+    FileType, Hyperparameters, LoadError, LoadProgress, Vocabulary,
+};
+
+/// A single tensor's data and shape, as loaded from one part of a sharded model.
+pub struct PartTensor {
+    /// The tensor's name.
+    pub name: String,
+    /// The tensor's shape, outermost dimension first.
+    pub shape: Vec<usize>,
+    /// The tensor's raw data.
+    pub data: Vec<u8>,
+    /// The size, in bytes, of a single element. For block-quantized types this is the block's
+    /// average bytes-per-element, rounded up; merging works from `data`'s actual length instead.
+    pub element_size: usize,
+    /// The raw ggml element type tag, matching the convention used elsewhere in this crate
+    /// (e.g. [`crate::gguf::GgufTensorInfo::element_type`]).
+    pub element_type: i32,
+}
+
+/// Infers which dimension a tensor was split along by comparing the shapes reported by each
+/// part. Returns `None` if the tensor was duplicated (identical shape in every part) rather
+/// than split.
+fn infer_split_axis(shapes: &[Vec<usize>]) -> Option<usize> {
+    let first = shapes.first()?;
+    (0..first.len()).find(|&axis| shapes.iter().any(|shape| shape[axis] != first[axis]))
+}
+
+/// Merges the per-part tensors for a single tensor name into one tensor, concatenating along
+/// the inferred split axis or simply returning the first part's data if the tensor was
+/// duplicated across parts rather than split.
+fn merge_tensor_parts(
+    tensor_name: &str,
+    parts: Vec<PartTensor>,
+) -> Result<PartTensor, LoadError> {
+    let shapes: Vec<Vec<usize>> = parts.iter().map(|part| part.shape.clone()).collect();
+
+    let Some(split_axis) = infer_split_axis(&shapes) else {
+        // The tensor is identical across every part (e.g. it was duplicated, not split);
+        // any one copy is the merged result.
+        return Ok(parts.into_iter().next().expect("parts is non-empty"));
+    };
+
+    // Every dimension other than the split axis must agree across parts.
+    let first_shape = &shapes[0];
+    for shape in &shapes {
+        if shape.len() != first_shape.len()
+            || shape
+                .iter()
+                .enumerate()
+                .any(|(axis, &dim)| axis != split_axis && dim != first_shape[axis])
+        {
+            return Err(LoadError::InconsistentShardShapes {
+                tensor_name: tensor_name.to_owned(),
+                shapes,
+            });
+        }
+    }
+
+    let element_size = parts[0].element_size;
+    let element_type = parts[0].element_type;
+    let mut merged_shape = first_shape.clone();
+    merged_shape[split_axis] = shapes.iter().map(|shape| shape[split_axis]).sum();
+
+    // Row-major data is laid out as `outer_size` repetitions of one slab per part, where
+    // `outer_size` is the element count of the dimensions before the split axis. Splitting along
+    // the outermost dimension (axis 0, `outer_size == 1`) degenerates to a single contiguous
+    // append per part, but any other axis requires interleaving: part 0's slab for outer index
+    // `i`, then part 1's slab for the same `i`, and so on, before moving on to `i + 1`. Slab
+    // sizes come from each part's actual data length rather than from `element_size`, so
+    // block-quantized data is copied byte-exactly.
+    let outer_size: usize = first_shape[..split_axis].iter().product();
+    let chunk_sizes: Vec<usize> = parts.iter().map(|part| part.data.len() / outer_size.max(1)).collect();
+    let dest_outer_stride: usize = chunk_sizes.iter().sum();
+    let mut data = vec![0u8; outer_size * dest_outer_stride];
+
+    for outer in 0..outer_size {
+        let mut dest_start = outer * dest_outer_stride;
+        for (part, &chunk_bytes) in parts.iter().zip(&chunk_sizes) {
+            let src_start = outer * chunk_bytes;
+            data[dest_start..dest_start + chunk_bytes]
+                .copy_from_slice(&part.data[src_start..src_start + chunk_bytes]);
+
+            dest_start += chunk_bytes;
+        }
+    }
+
+    Ok(PartTensor {
+        name: tensor_name.to_owned(),
+        shape: merged_shape,
+        data,
+        element_size,
+        element_type,
+    })
+}
+
+/// Loads and merges all parts of a sharded model, given the already-discovered part paths (in
+/// part order) and a callback that loads a single part's tensors.
+///
+/// Emits [`LoadProgress::PartLoading`] before loading each part and [`LoadProgress::PartLoaded`]
+/// after, matching the single-file loading progress story.
+pub fn load_and_merge_parts(
+    paths: &[PathBuf],
+    mut load_part: impl FnMut(&Path) -> Result<Vec<PartTensor>, LoadError>,
+    mut progress_callback: impl FnMut(LoadProgress),
+) -> Result<Vec<PartTensor>, LoadError> {
+    let mut by_name: std::collections::HashMap<String, Vec<PartTensor>> =
+        std::collections::HashMap::new();
+
+    for (current_part, path) in paths.iter().enumerate() {
+        progress_callback(LoadProgress::PartLoading {
+            file: path,
+            current_part,
+            total_parts: paths.len(),
+        });
+
+        let tensors = load_part(path)?;
+        let tensor_count = tensors.len();
+        let byte_size = tensors.iter().map(|tensor| tensor.data.len()).sum();
+        for tensor in tensors {
+            by_name.entry(tensor.name.clone()).or_default().push(tensor);
+        }
+
+        progress_callback(LoadProgress::PartLoaded {
+            file: path,
+            byte_size,
+            tensor_count,
+        });
+    }
+
+    by_name
+        .into_iter()
+        .map(|(name, parts)| merge_tensor_parts(&name, parts))
+        .collect()
+}
+
+/// Writes a sharded model's already-merged tensors out as a single-file GGML model, via
+/// [`crate::saver::save_model`]. This is the write-side counterpart to
+/// [`load_and_merge_parts`]: rather than re-splitting a single-file model into shards, it lets a
+/// caller collapse a sharded model into one file, for example to avoid re-merging it on every
+/// future load.
+///
+/// `element_types` supplies each tensor's [`ggml::Type`], by name; [`PartTensor::element_type`]
+/// (a raw ggml tag) is not consulted. A tensor present in `merged` but missing from
+/// `element_types` fails with [`SaveError::MissingElementType`].
+pub fn merge_to_single_file(
+    writer: &mut (impl std::io::Write + std::io::Seek),
+    hyperparameters: &Hyperparameters,
+    file_type: FileType,
+    vocabulary: &Vocabulary,
+    merged: &[PartTensor],
+    element_types: &std::collections::HashMap<String, ggml::Type>,
+    align_tensor_data: bool,
+    progress_callback: impl FnMut(SaveProgress),
+) -> Result<(), SaveError> {
+    let tensors = merged
+        .iter()
+        .map(|tensor| {
+            let element_type = *element_types
+                .get(&tensor.name)
+                .ok_or_else(|| SaveError::MissingElementType {
+                    tensor_name: tensor.name.clone(),
+                })?;
+            Ok(TensorSaveInfo {
+                name: tensor.name.clone(),
+                dims: tensor.shape.clone(),
+                element_type,
+                data: &tensor.data,
+            })
+        })
+        .collect::<Result<Vec<_>, SaveError>>()?;
+
+    saver::save_model(
+        writer,
+        hyperparameters,
+        file_type,
+        vocabulary,
+        &tensors,
+        align_tensor_data,
+        progress_callback,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(shape: Vec<usize>, data: Vec<u8>) -> PartTensor {
+        PartTensor {
+            name: "t".to_owned(),
+            shape,
+            data,
+            element_size: 1,
+            element_type: 0, // f32
+        }
+    }
+
+    #[test]
+    fn merges_a_row_split_tensor() {
+        // A `[4, 2]` tensor split unevenly into a `[3, 2]` part and a `[1, 2]` part along axis 0
+        // (an even row split would make every part's shape identical, which is indistinguishable
+        // from a duplicated tensor by shape alone).
+        let parts = vec![part(vec![3, 2], vec![1, 2, 3, 4, 5, 6]), part(vec![1, 2], vec![7, 8])];
+
+        let merged = merge_tensor_parts("t", parts).unwrap();
+
+        assert_eq!(merged.shape, vec![4, 2]);
+        assert_eq!(merged.data, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn merges_a_column_split_tensor() {
+        // A `[2, 4]` tensor split unevenly into a `[2, 3]` part and a `[2, 1]` part along axis 1
+        // (an even column split would make every part's shape identical, which is
+        // indistinguishable from a duplicated tensor by shape alone).
+        let parts = vec![part(vec![2, 3], vec![1, 2, 3, 5, 6, 7]), part(vec![2, 1], vec![4, 8])];
+
+        let merged = merge_tensor_parts("t", parts).unwrap();
+
+        assert_eq!(merged.shape, vec![2, 4]);
+        assert_eq!(merged.data, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn duplicated_tensors_are_passed_through_unmerged() {
+        let parts = vec![part(vec![2, 2], vec![1, 2, 3, 4]), part(vec![2, 2], vec![1, 2, 3, 4])];
+
+        let merged = merge_tensor_parts("t", parts).unwrap();
+
+        assert_eq!(merged.shape, vec![2, 2]);
+        assert_eq!(merged.data, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_inconsistent_shard_shapes() {
+        // More than one axis differs, so no single split axis explains the shapes.
+        let parts = vec![part(vec![2, 2], vec![1, 2, 3, 4]), part(vec![3, 3], vec![0; 9])];
+
+        let err = merge_tensor_parts("t", parts).unwrap_err();
+        assert!(matches!(err, LoadError::InconsistentShardShapes { .. }));
+    }
+}
@@ -0,0 +1,768 @@
+//! Support for quantizing F32/F16 GGML models into the various `Q4`/`Q5`/`Q8` `FileType`s.
+
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+
+use thiserror::Error;
+
+use crate::{
+    loader::{self, GGJT_MAGIC, GGMF_MAGIC, GGML_MAGIC},
+    saver::{self, TensorSaveInfo},
+    FileType, LoadError,
+};
+
+/// The size, in elements, of a single quantization block.
+///
+/// Every quantizer in this module operates on rows of weights in chunks of this many
+/// elements; a row whose element count is not a multiple of this is rejected.
+const QK: usize = 32;
+
+/// The raw ggml element type tag used for f32 data, matching `FileType::F32`'s discriminant.
+pub(crate) const ELEMENT_TYPE_F32: i32 = 0;
+/// The raw ggml element type tag used for f16 data, matching `FileType::MostlyF16`'s discriminant.
+pub(crate) const ELEMENT_TYPE_F16: i32 = 1;
+
+/// Each variant represents a step within the process of quantizing a model.
+///
+/// These can be used to report progress to the user.
+#[derive(Clone, PartialEq, Debug)]
+pub enum QuantizeProgress<'a> {
+    /// A tensor is being quantized (or, for 1D tensors, written through as f32).
+    TensorQuantizing {
+        /// The name of the tensor.
+        name: &'a str,
+    },
+    /// A tensor has finished quantizing.
+    TensorQuantized {
+        /// The name of the tensor.
+        name: &'a str,
+        /// The number of elements in the tensor.
+        element_count: usize,
+        /// The number of bytes the quantized tensor occupies in the output file.
+        byte_size: usize,
+    },
+    /// A tensor was skipped because it is not quantizable (e.g. it is not 2D).
+    TensorSkipped {
+        /// The name of the tensor.
+        name: &'a str,
+        /// The number of elements in the tensor.
+        element_count: usize,
+    },
+    /// The quantization process has finished.
+    Finished,
+}
+
+#[derive(Error, Debug)]
+/// Errors encountered during the quantization process.
+pub enum QuantizeError {
+    #[error("non-specific I/O error")]
+    /// A non-specific IO error.
+    Io(#[from] std::io::Error),
+    #[error("could not convert bytes to a UTF-8 string")]
+    /// One of the strings encountered was not valid UTF-8.
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    #[error("invalid integer conversion")]
+    /// One of the integers encountered could not be converted to a more appropriate type.
+    InvalidIntegerConversion(#[from] std::num::TryFromIntError),
+    #[error("could not load model")]
+    /// There was an error loading the source model that was being quantized.
+    Load(#[from] crate::LoadError),
+    #[error("non-quantizable element type {element_type} encountered for tensor `{tensor_name}`")]
+    /// The source tensor's element type cannot be quantized: only f32 and f16 source tensors
+    /// are supported.
+    UnsupportedElementType {
+        /// The name of the tensor.
+        tensor_name: String,
+        /// The raw ggml element type tag that was encountered.
+        element_type: i32,
+    },
+    #[error(
+        "tensor `{tensor_name}` has {n_elements} elements per row, which is not a multiple of {QK}"
+    )]
+    /// A tensor's row length is not a multiple of the block size.
+    InvalidRowLength {
+        /// The name of the tensor.
+        tensor_name: String,
+        /// The number of elements in a row of the tensor.
+        n_elements: usize,
+    },
+    #[error("invalid target quantization format {target:?}")]
+    /// The requested `FileType` is not a supported quantization target.
+    UnsupportedFileType {
+        /// The `FileType` that was requested.
+        target: FileType,
+    },
+    #[error("could not save model")]
+    /// There was an error writing a quantized tensor's info block or data.
+    Save(#[from] crate::saver::SaveError),
+}
+
+/// A single `Q4_0` block: 32 4-bit weights, prefixed by an f16 scale.
+struct BlockQ4_0 {
+    /// The scale, `amax / -8`, stored as an f16.
+    d: half::f16,
+    /// The 32 packed 4-bit weights, two per byte.
+    qs: [u8; QK / 2],
+}
+
+/// A single `Q4_1` block: 32 4-bit weights, prefixed by an f16 scale and minimum.
+struct BlockQ4_1 {
+    /// The scale, `(max - min) / 15`, stored as an f16.
+    d: half::f16,
+    /// The minimum value of the block, stored as an f16.
+    m: half::f16,
+    /// The 32 packed 4-bit weights, two per byte.
+    qs: [u8; QK / 2],
+}
+
+/// A single `Q8_0` block: 32 signed 8-bit weights, prefixed by an f16 scale.
+struct BlockQ8_0 {
+    /// The scale, `amax / 127`, stored as an f16.
+    d: half::f16,
+    /// The 32 signed 8-bit weights.
+    qs: [i8; QK],
+}
+
+/// Checks that `target` is one of the block formats this module can quantize to/from.
+fn check_is_quantizable_format(target: FileType) -> Result<(), QuantizeError> {
+    match target {
+        FileType::MostlyQ4_0 | FileType::MostlyQ4_1 | FileType::MostlyQ8_0 => Ok(()),
+        other => Err(QuantizeError::UnsupportedFileType { target: other }),
+    }
+}
+
+/// The number of bytes a single block of `target`'s format occupies on disk.
+///
+/// Panics if `target` is not a format this module supports; callers must validate with
+/// [`check_is_quantizable_format`] first.
+pub(crate) fn block_byte_size(target: FileType) -> usize {
+    match target {
+        FileType::MostlyQ4_0 => 2 + QK / 2,
+        FileType::MostlyQ4_1 => 2 + 2 + QK / 2,
+        FileType::MostlyQ8_0 => 2 + QK,
+        _ => unreachable!("caller must have already validated `target`"),
+    }
+}
+
+fn quantize_row_q4_0(row: &[f32]) -> Vec<BlockQ4_0> {
+    row.chunks_exact(QK)
+        .map(|block| {
+            let amax = block.iter().copied().fold(0.0f32, |a, b| a.max(b.abs()));
+            let d = amax / -8.0;
+            let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+
+            let mut qs = [0u8; QK / 2];
+            for (i, pair) in block.chunks(2).enumerate() {
+                let x0 = (pair[0] * id + 8.5).clamp(0.0, 15.0) as u8;
+                let x1 = (pair[1] * id + 8.5).clamp(0.0, 15.0) as u8;
+                qs[i] = x0 | (x1 << 4);
+            }
+
+            BlockQ4_0 {
+                d: half::f16::from_f32(d),
+                qs,
+            }
+        })
+        .collect()
+}
+
+fn quantize_row_q4_1(row: &[f32]) -> Vec<BlockQ4_1> {
+    row.chunks_exact(QK)
+        .map(|block| {
+            let min = block.iter().copied().fold(f32::MAX, f32::min);
+            let max = block.iter().copied().fold(f32::MIN, f32::max);
+            let d = (max - min) / 15.0;
+            let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+
+            let mut qs = [0u8; QK / 2];
+            for (i, pair) in block.chunks(2).enumerate() {
+                let x0 = ((pair[0] - min) * id + 0.5).clamp(0.0, 15.0) as u8;
+                let x1 = ((pair[1] - min) * id + 0.5).clamp(0.0, 15.0) as u8;
+                qs[i] = x0 | (x1 << 4);
+            }
+
+            BlockQ4_1 {
+                d: half::f16::from_f32(d),
+                m: half::f16::from_f32(min),
+                qs,
+            }
+        })
+        .collect()
+}
+
+fn quantize_row_q8_0(row: &[f32]) -> Vec<BlockQ8_0> {
+    row.chunks_exact(QK)
+        .map(|block| {
+            let amax = block.iter().copied().fold(0.0f32, |a, b| a.max(b.abs()));
+            let d = amax / 127.0;
+            let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+
+            let mut qs = [0i8; QK];
+            for (q, &x) in qs.iter_mut().zip(block) {
+                *q = (x * id).round() as i8;
+            }
+
+            BlockQ8_0 {
+                d: half::f16::from_f32(d),
+                qs,
+            }
+        })
+        .collect()
+}
+
+fn write_blocks_q4_0(writer: &mut impl Write, blocks: &[BlockQ4_0]) -> Result<(), QuantizeError> {
+    for block in blocks {
+        writer.write_all(&block.d.to_le_bytes())?;
+        writer.write_all(&block.qs)?;
+    }
+    Ok(())
+}
+
+fn write_blocks_q4_1(writer: &mut impl Write, blocks: &[BlockQ4_1]) -> Result<(), QuantizeError> {
+    for block in blocks {
+        writer.write_all(&block.d.to_le_bytes())?;
+        writer.write_all(&block.m.to_le_bytes())?;
+        writer.write_all(&block.qs)?;
+    }
+    Ok(())
+}
+
+fn write_blocks_q8_0(writer: &mut impl Write, blocks: &[BlockQ8_0]) -> Result<(), QuantizeError> {
+    for block in blocks {
+        writer.write_all(&block.d.to_le_bytes())?;
+        for &q in &block.qs {
+            writer.write_all(&[q as u8])?;
+        }
+    }
+    Ok(())
+}
+
+/// Quantizes a row of f32 weights into `target`'s block format, writing the result to `writer`.
+///
+/// `row` must be a multiple of the block size (32); rows that describe a single quantization
+/// block's worth of weights at a time should be passed in one call each.
+fn quantize_row(
+    target: FileType,
+    tensor_name: &str,
+    row: &[f32],
+    writer: &mut impl Write,
+) -> Result<(), QuantizeError> {
+    if row.len() % QK != 0 {
+        return Err(QuantizeError::InvalidRowLength {
+            tensor_name: tensor_name.to_owned(),
+            n_elements: row.len(),
+        });
+    }
+
+    match target {
+        FileType::MostlyQ4_0 => write_blocks_q4_0(writer, &quantize_row_q4_0(row)),
+        FileType::MostlyQ4_1 => write_blocks_q4_1(writer, &quantize_row_q4_1(row)),
+        FileType::MostlyQ8_0 => write_blocks_q8_0(writer, &quantize_row_q8_0(row)),
+        other => Err(QuantizeError::UnsupportedFileType { target: other }),
+    }
+}
+
+/// Quantizes every row of `values` (row-major, `row_len` elements per row) into `target`'s
+/// block format, returning the concatenated output bytes. Used by callers, such as
+/// [`crate::lora`], that already have a full tensor's values in memory rather than streaming
+/// from a reader.
+pub(crate) fn quantize_values(
+    target: FileType,
+    tensor_name: &str,
+    row_len: usize,
+    values: &[f32],
+) -> Result<Vec<u8>, QuantizeError> {
+    check_is_quantizable_format(target)?;
+    let mut out = Vec::with_capacity((values.len() / QK) * block_byte_size(target));
+    for row in values.chunks_exact(row_len) {
+        quantize_row(target, tensor_name, row, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn dequantize_block_q4_0(block: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    let d = half::f16::from_le_bytes(block[0..2].try_into().unwrap()).to_f32();
+    block[2..].iter().flat_map(move |&byte| {
+        let lo = (byte & 0x0f) as f32 - 8.0;
+        let hi = (byte >> 4) as f32 - 8.0;
+        [lo * d, hi * d]
+    })
+}
+
+fn dequantize_block_q4_1(block: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    let d = half::f16::from_le_bytes(block[0..2].try_into().unwrap()).to_f32();
+    let m = half::f16::from_le_bytes(block[2..4].try_into().unwrap()).to_f32();
+    block[4..].iter().flat_map(move |&byte| {
+        let lo = (byte & 0x0f) as f32 * d + m;
+        let hi = (byte >> 4) as f32 * d + m;
+        [lo, hi]
+    })
+}
+
+fn dequantize_block_q8_0(block: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    let d = half::f16::from_le_bytes(block[0..2].try_into().unwrap()).to_f32();
+    block[2..].iter().map(move |&byte| byte as i8 as f32 * d)
+}
+
+/// Dequantizes `source`'s block format back to f32, inverting [`quantize_values`]. Used when a
+/// quantized base tensor needs to be dequantized (e.g. before a LoRA delta can be added) and,
+/// afterward, requantized with [`quantize_values`].
+pub(crate) fn dequantize_values(source: FileType, bytes: &[u8]) -> Result<Vec<f32>, QuantizeError> {
+    check_is_quantizable_format(source)?;
+    let block_size = block_byte_size(source);
+    if bytes.len() % block_size != 0 {
+        return Err(QuantizeError::InvalidRowLength {
+            tensor_name: String::new(),
+            n_elements: bytes.len(),
+        });
+    }
+
+    Ok(match source {
+        FileType::MostlyQ4_0 => bytes
+            .chunks_exact(block_size)
+            .flat_map(dequantize_block_q4_0)
+            .collect(),
+        FileType::MostlyQ4_1 => bytes
+            .chunks_exact(block_size)
+            .flat_map(dequantize_block_q4_1)
+            .collect(),
+        FileType::MostlyQ8_0 => bytes
+            .chunks_exact(block_size)
+            .flat_map(dequantize_block_q8_0)
+            .collect(),
+        other => return Err(QuantizeError::UnsupportedFileType { target: other }),
+    })
+}
+
+/// A single tensor read from (or about to be written to) the source/target tensor stream.
+///
+/// This mirrors the tensor info block used by [`crate::saver`]: a name, dims (outermost
+/// first), a raw ggml element type tag, and the tensor's raw data.
+struct StreamTensor {
+    name: String,
+    dims: Vec<usize>,
+    element_type: i32,
+    data: Vec<u8>,
+}
+impl StreamTensor {
+    fn element_count(&self) -> usize {
+        self.dims.iter().product()
+    }
+
+    /// The number of elements in a single row (the innermost dimension), or the whole tensor
+    /// if it is 1D.
+    fn row_len(&self) -> usize {
+        *self.dims.last().unwrap_or(&self.element_count())
+    }
+
+    /// Returns this tensor's data as f32, upconverting from f16 if necessary.
+    fn as_f32(&self) -> Result<Vec<f32>, QuantizeError> {
+        match self.element_type {
+            ELEMENT_TYPE_F32 => Ok(self
+                .data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect()),
+            ELEMENT_TYPE_F16 => Ok(self
+                .data
+                .chunks_exact(2)
+                .map(|b| half::f16::from_le_bytes(b.try_into().unwrap()).to_f32())
+                .collect()),
+            other => Err(QuantizeError::UnsupportedElementType {
+                tensor_name: self.name.clone(),
+                element_type: other,
+            }),
+        }
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, QuantizeError> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i32(reader: &mut impl Read) -> Result<i32, QuantizeError> {
+    Ok(read_u32(reader)? as i32)
+}
+
+/// Reads a single tensor from the source stream's positional tensor directory -- the same
+/// on-disk layout [`crate::loader::read_legacy_tensor_directory`] walks for loading, interleaving
+/// each tensor's data right after its info block rather than separating the directory from the
+/// data as GGUF does. Returns `Ok(None)` once the directory is exhausted.
+///
+/// `align32` pads each tensor's data to a 32-byte boundary, matching the GGJT container; GGML/GGMF
+/// have no such padding.
+fn read_source_tensor(
+    reader: &mut (impl BufRead + Seek),
+    align32: bool,
+) -> Result<Option<StreamTensor>, QuantizeError> {
+    let mut word = [0u8; 4];
+    if !loader::read_exact_or_eof(reader, &mut word)? {
+        return Ok(None);
+    }
+    let n_dims = u32::from_le_bytes(word);
+
+    let name_len = usize::try_from(read_u32(reader)?)?;
+    let element_type = read_i32(reader)?;
+
+    let mut dims = Vec::with_capacity(n_dims as usize);
+    for _ in 0..n_dims {
+        dims.push(usize::try_from(read_u32(reader)?)?);
+    }
+
+    let mut name_buf = vec![0u8; name_len];
+    reader.read_exact(&mut name_buf)?;
+    let name = String::from_utf8(name_buf)?;
+
+    if align32 {
+        let position = reader.stream_position()?;
+        let padding = (32 - (position % 32)) % 32;
+        reader.seek(SeekFrom::Current(padding as i64))?;
+    }
+
+    let dims_u64: Vec<u64> = dims.iter().map(|&dim| dim as u64).collect();
+    let data_len = loader::tensor_byte_len(&dims_u64, element_type);
+    let mut data = vec![0u8; data_len];
+    reader.read_exact(&mut data)?;
+
+    Ok(Some(StreamTensor {
+        name,
+        dims,
+        element_type,
+        data,
+    }))
+}
+
+/// The [`ggml::Type`] a raw ggml element type tag names, for the handful of types
+/// [`TensorSaveInfo`] needs one for. Mirrors [`FileType`]'s own `TryFrom<i32>` mapping.
+fn ggml_type_from_raw(tensor_name: &str, element_type: i32) -> Result<ggml::Type, QuantizeError> {
+    Ok(match element_type {
+        0 => ggml::Type::F32,
+        1 => ggml::Type::F16,
+        2 => ggml::Type::Q4_0,
+        3 => ggml::Type::Q4_1,
+        4 => ggml::Type::Q4_2,
+        5 => ggml::Type::Q4_3,
+        6 => ggml::Type::Q5_0,
+        7 => ggml::Type::Q5_1,
+        8 => ggml::Type::Q8_0,
+        9 => ggml::Type::Q8_1,
+        other => {
+            return Err(QuantizeError::UnsupportedElementType {
+                tensor_name: tensor_name.to_owned(),
+                element_type: other,
+            })
+        }
+    })
+}
+
+/// Quantizes 2D tensor `tensor`'s rows to `target`'s block format and writes the resulting
+/// info block and data to `writer`, via [`saver::write_tensor`]. Returns the number of data
+/// bytes written (not counting the info block or any alignment padding).
+fn quantize_and_write_tensor(
+    tensor: &StreamTensor,
+    target: FileType,
+    align32: bool,
+    writer: &mut (impl Write + Seek),
+) -> Result<usize, QuantizeError> {
+    let values = tensor.as_f32()?;
+    let row_len = tensor.row_len();
+    if row_len == 0 {
+        // `row_len` is the tensor's innermost dimension; a degenerate tensor with a
+        // zero-length innermost dimension would otherwise divide by zero computing `n_rows`
+        // below, before `quantize_row`'s own "multiple of `QK`" check ever got a chance to run.
+        return Err(QuantizeError::InvalidRowLength {
+            tensor_name: tensor.name.clone(),
+            n_elements: 0,
+        });
+    }
+    let n_rows = values.len() / row_len;
+    let byte_size = n_rows * (row_len / QK) * block_byte_size(target);
+
+    let mut data = Vec::with_capacity(byte_size);
+    for row in values.chunks_exact(row_len) {
+        quantize_row(target, &tensor.name, row, &mut data)?;
+    }
+
+    let element_type = ggml_type_from_raw(&tensor.name, i32::from(target))?;
+    saver::write_tensor(
+        writer,
+        &TensorSaveInfo {
+            name: tensor.name.clone(),
+            dims: tensor.dims.clone(),
+            element_type,
+            data: &data,
+        },
+        align32,
+    )?;
+
+    Ok(byte_size)
+}
+
+/// Writes `tensor` through to `writer` unquantized, upconverted to f32, via
+/// [`saver::write_tensor`]. Returns the number of data bytes written (not counting the info
+/// block or any alignment padding).
+fn write_tensor_as_f32(
+    tensor: &StreamTensor,
+    align32: bool,
+    writer: &mut (impl Write + Seek),
+) -> Result<usize, QuantizeError> {
+    let values = tensor.as_f32()?;
+    let byte_size = values.len() * 4;
+    let data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+
+    saver::write_tensor(
+        writer,
+        &TensorSaveInfo {
+            name: tensor.name.clone(),
+            dims: tensor.dims.clone(),
+            element_type: ggml::Type::F32,
+            data: &data,
+        },
+        align32,
+    )?;
+
+    Ok(byte_size)
+}
+
+/// Copies a legacy container's hyperparameters and vocabulary sections from `reader` to `writer`
+/// verbatim, except for the trailing `ftype` hyperparameter, which is replaced with `target`'s
+/// discriminant so the output file's header honestly reflects what [`quantize`] wrote. Mirrors
+/// the section [`crate::loader::skip_legacy_hyperparameters_and_vocab`] skips when loading.
+fn copy_hyperparameters_and_vocabulary(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    target: FileType,
+) -> Result<(), QuantizeError> {
+    let mut word = [0u8; 4];
+
+    reader.read_exact(&mut word)?;
+    let n_vocab = u32::from_le_bytes(word);
+    writer.write_all(&word)?;
+
+    for _ in 0..5 {
+        // n_embd, n_mult, n_head, n_layer, n_rot.
+        reader.read_exact(&mut word)?;
+        writer.write_all(&word)?;
+    }
+
+    reader.read_exact(&mut word)?; // ftype, discarded in favor of `target` below.
+    writer.write_all(&i32::from(target).to_le_bytes())?;
+
+    for _ in 0..n_vocab {
+        reader.read_exact(&mut word)?;
+        writer.write_all(&word)?;
+        let token_len = u32::from_le_bytes(word);
+
+        let mut token = vec![0u8; token_len as usize];
+        reader.read_exact(&mut token)?;
+        writer.write_all(&token)?;
+
+        reader.read_exact(&mut word)?; // the token's f32 score
+        writer.write_all(&word)?;
+    }
+
+    Ok(())
+}
+
+/// Streams tensors from a source legacy GGML/GGMF/GGJT file and writes a new container of the
+/// same kind, with 2D weight tensors quantized to `target`'s block format; 1D tensors (biases,
+/// norms, etc.) are left as f32.
+///
+/// The source and output streams both use the real container format parsed elsewhere in this
+/// crate ([`crate::loader`]/[`crate::saver`]): magic, version, hyperparameters, vocabulary, and a
+/// positional tensor directory. `target`'s discriminant is recorded as the output's `ftype`
+/// hyperparameter and as each quantized tensor's element type tag. `progress_callback` is called
+/// for every tensor as it is processed, and once more when quantization is complete.
+pub fn quantize(
+    reader: &mut (impl BufRead + Seek),
+    writer: &mut (impl Write + Seek),
+    target: FileType,
+    mut progress_callback: impl FnMut(QuantizeProgress),
+) -> Result<(), QuantizeError> {
+    check_is_quantizable_format(target)?;
+
+    let mut magic_bytes = [0u8; 4];
+    reader.read_exact(&mut magic_bytes)?;
+    let magic = u32::from_le_bytes(magic_bytes);
+
+    let align32 = match magic {
+        GGML_MAGIC | GGMF_MAGIC => false,
+        GGJT_MAGIC => true,
+        other => {
+            return Err(QuantizeError::Load(LoadError::InvalidMagic {
+                path: Default::default(),
+                magic: other,
+            }))
+        }
+    };
+    writer.write_all(&magic_bytes)?;
+
+    if magic != GGML_MAGIC {
+        let mut version_bytes = [0u8; 4];
+        reader.read_exact(&mut version_bytes)?;
+        writer.write_all(&version_bytes)?;
+    }
+
+    copy_hyperparameters_and_vocabulary(reader, writer, target)?;
+
+    while let Some(tensor) = read_source_tensor(reader, align32)? {
+        progress_callback(QuantizeProgress::TensorQuantizing { name: &tensor.name });
+
+        let byte_size = if tensor.dims.len() >= 2 {
+            quantize_and_write_tensor(&tensor, target, align32, writer)?
+        } else {
+            progress_callback(QuantizeProgress::TensorSkipped {
+                name: &tensor.name,
+                element_count: tensor.element_count(),
+            });
+            write_tensor_as_f32(&tensor, align32, writer)?
+        };
+
+        progress_callback(QuantizeProgress::TensorQuantized {
+            name: &tensor.name,
+            element_count: tensor.element_count(),
+            byte_size,
+        });
+    }
+
+    progress_callback(QuantizeProgress::Finished);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::Path};
+
+    use super::*;
+
+    /// Builds a minimal (empty vocabulary) GGJT-container source stream, in the same positional
+    /// format [`quantize`] now reads, with `tensors` written one after another (32-byte aligned).
+    fn sample_ggjt_source(tensors: &[(&str, &[usize], i32, &[u8])]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&GGJT_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&3u32.to_le_bytes()); // version
+
+        buf.extend_from_slice(&0u32.to_le_bytes()); // n_vocab
+        for _ in 0..5 {
+            buf.extend_from_slice(&0u32.to_le_bytes()); // n_embd, n_mult, n_head, n_layer, n_rot
+        }
+        buf.extend_from_slice(&i32::from(FileType::MostlyF16).to_le_bytes()); // ftype (source's own, overwritten on output)
+
+        for &(name, dims, element_type, data) in tensors {
+            buf.extend_from_slice(&(dims.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&element_type.to_le_bytes());
+            for &dim in dims {
+                buf.extend_from_slice(&(dim as u32).to_le_bytes());
+            }
+            buf.extend_from_slice(name.as_bytes());
+
+            let padding = (32 - (buf.len() % 32)) % 32;
+            buf.extend(std::iter::repeat(0u8).take(padding));
+            buf.extend_from_slice(data);
+        }
+
+        buf
+    }
+
+    /// Reads back `output`'s `ftype` hyperparameter and tensor directory via the production
+    /// loading path ([`crate::loader`]), proving the two halves of the crate agree on the
+    /// container format.
+    fn read_output_ftype_and_tensors(
+        output: &[u8],
+    ) -> (i32, Vec<(String, Vec<usize>, i32, Vec<u8>)>) {
+        let mut cursor = Cursor::new(output);
+        let header = loader::read_container_header(&mut cursor).unwrap();
+        assert!(matches!(header, loader::ContainerHeader::Legacy(_)));
+
+        let mut word = [0u8; 4];
+        cursor.read_exact(&mut word).unwrap(); // n_vocab
+        assert_eq!(u32::from_le_bytes(word), 0);
+        for _ in 0..5 {
+            cursor.read_exact(&mut word).unwrap(); // n_embd, n_mult, n_head, n_layer, n_rot
+        }
+        cursor.read_exact(&mut word).unwrap();
+        let ftype = i32::from_le_bytes(word);
+
+        let tensor_infos = loader::read_legacy_tensor_directory(&mut cursor, true).unwrap();
+        let resolved =
+            loader::resolve_tensor_data(Path::new("unused"), &mut cursor, &tensor_infos, false, |_| {})
+                .unwrap();
+
+        let tensors = tensor_infos
+            .into_iter()
+            .zip(resolved)
+            .map(|(info, data)| {
+                (
+                    info.name,
+                    info.dims.iter().map(|&dim| dim as usize).collect(),
+                    info.element_type,
+                    data.as_slice().to_vec(),
+                )
+            })
+            .collect();
+
+        (ftype, tensors)
+    }
+
+    #[test]
+    fn quantizes_a_2d_tensor_and_passes_through_a_1d_tensor() {
+        let weight_values: Vec<f32> = (0..64).map(|i| (i as f32 - 32.0) / 4.0).collect();
+        let weight_bytes: Vec<u8> = weight_values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let bias_values = [1.0f32, 2.0, 3.0];
+        let bias_bytes: Vec<u8> = bias_values.iter().flat_map(|v| v.to_le_bytes()).collect();
+
+        let source = sample_ggjt_source(&[
+            ("weight", &[1, 64], ELEMENT_TYPE_F32, &weight_bytes),
+            ("bias", &[3], ELEMENT_TYPE_F32, &bias_bytes),
+        ]);
+
+        let mut reader = Cursor::new(source);
+        let mut output = Cursor::new(Vec::new());
+        let mut events = Vec::new();
+        quantize(&mut reader, &mut output, FileType::MostlyQ4_0, |p| {
+            events.push(format!("{p:?}"))
+        })
+        .unwrap();
+
+        let (ftype, tensors) = read_output_ftype_and_tensors(&output.into_inner());
+        assert_eq!(ftype, i32::from(FileType::MostlyQ4_0));
+
+        let weight = tensors.iter().find(|(name, ..)| name == "weight").unwrap();
+        assert_eq!(weight.2, i32::from(FileType::MostlyQ4_0));
+        assert_eq!(weight.3.len(), 2 * block_byte_size(FileType::MostlyQ4_0));
+
+        let bias = tensors.iter().find(|(name, ..)| name == "bias").unwrap();
+        assert_eq!(bias.2, ELEMENT_TYPE_F32);
+        assert_eq!(bias.3, bias_bytes);
+
+        assert!(events.iter().any(|e| e.contains("TensorSkipped")));
+        assert!(events.iter().any(|e| e.contains("Finished")));
+    }
+
+    #[test]
+    fn rejects_a_row_length_that_is_not_a_multiple_of_the_block_size() {
+        let values = vec![0.0f32; 17];
+        let err = quantize_row(FileType::MostlyQ4_0, "t", &values, &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, QuantizeError::InvalidRowLength { n_elements: 17, .. }));
+    }
+
+    #[test]
+    fn rejects_a_tensor_whose_innermost_dimension_is_zero_instead_of_panicking() {
+        let source = sample_ggjt_source(&[("degenerate", &[4, 0], ELEMENT_TYPE_F32, &[])]);
+        let mut reader = Cursor::new(source);
+        let mut output = Cursor::new(Vec::new());
+
+        let err = quantize(&mut reader, &mut output, FileType::MostlyQ4_0, |_| {}).unwrap_err();
+        assert!(matches!(err, QuantizeError::InvalidRowLength { n_elements: 0, .. }));
+    }
+
+    #[test]
+    fn rejects_an_unsupported_target_file_type() {
+        let mut reader = Cursor::new(Vec::new());
+        let mut writer = Cursor::new(Vec::new());
+        let err = quantize(&mut reader, &mut writer, FileType::MostlyF16, |_| {}).unwrap_err();
+        assert!(matches!(err, QuantizeError::UnsupportedFileType { .. }));
+    }
+}
@@ -0,0 +1,238 @@
+//! Support for writing GGML model files, symmetric to the loading support in
+//! [`crate::loader_common`].
+
+use std::io::Write;
+
+use ggml_format::ContainerType;
+use thiserror::Error;
+
+use crate::{FileType, Hyperparameters, Vocabulary};
+
+/// Each variant represents a step within the process of saving a model.
+///
+/// These can be used to report progress to the user.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SaveProgress<'a> {
+    /// A tensor is being written.
+    TensorWriting {
+        /// The name of the tensor.
+        name: &'a str,
+    },
+    /// A tensor has finished writing.
+    TensorWritten {
+        /// The name of the tensor.
+        name: &'a str,
+        /// The number of bytes written for this tensor, including its info block.
+        byte_size: usize,
+    },
+    /// The save process has finished.
+    Finished {
+        /// The total number of bytes written.
+        total_bytes: usize,
+    },
+}
+
+#[derive(Error, Debug)]
+/// Errors encountered during the model saving process.
+pub enum SaveError {
+    #[error("non-specific I/O error")]
+    /// A non-specific IO error.
+    Io(#[from] std::io::Error),
+    #[error("invalid integer conversion")]
+    /// One of the integers encountered could not be converted to a more appropriate type.
+    InvalidIntegerConversion(#[from] std::num::TryFromIntError),
+    #[error("tensor `{tensor_name}` has element type {element_type:?}, which cannot be saved as `FileType` {file_type:?}")]
+    /// A tensor's element type is not consistent with the requested `FileType`.
+    ElementTypeFileTypeMismatch {
+        /// The name of the tensor.
+        tensor_name: String,
+        /// The tensor's element type.
+        element_type: ggml::Type,
+        /// The requested `FileType`.
+        file_type: FileType,
+    },
+    #[error("no element type was given for tensor `{tensor_name}`")]
+    /// A caller building [`TensorSaveInfo`] from some other representation (e.g.
+    /// [`crate::multipart::merge_to_single_file`]) did not have an element type on hand for this
+    /// tensor.
+    MissingElementType {
+        /// The name of the tensor.
+        tensor_name: String,
+    },
+}
+
+/// The element type 2D tensors must be stored as for `file_type` to be an honest description of
+/// the file's contents.
+fn expected_2d_element_type(file_type: FileType) -> ggml::Type {
+    match file_type {
+        FileType::F32 => ggml::Type::F32,
+        FileType::MostlyF16 => ggml::Type::F16,
+        FileType::MostlyQ4_0 => ggml::Type::Q4_0,
+        FileType::MostlyQ4_1 => ggml::Type::Q4_1,
+        FileType::MostlyQ4_2 => ggml::Type::Q4_2,
+        FileType::MostlyQ4_3 => ggml::Type::Q4_3,
+        FileType::MostlyQ5_0 => ggml::Type::Q5_0,
+        FileType::MostlyQ5_1 => ggml::Type::Q5_1,
+        FileType::MostlyQ8_0 => ggml::Type::Q8_0,
+        FileType::MostlyQ8_1 => ggml::Type::Q8_1,
+    }
+}
+
+/// Checks that `tensor`'s element type is consistent with `file_type`: 1D tensors (biases,
+/// norms, etc.) are always f32, and 2D (weight) tensors must match whatever `file_type` claims
+/// the file's tensors are "mostly" stored as.
+fn validate_element_type(tensor: &TensorSaveInfo, file_type: FileType) -> Result<(), SaveError> {
+    let expected = if tensor.dims.len() >= 2 {
+        expected_2d_element_type(file_type)
+    } else {
+        ggml::Type::F32
+    };
+
+    if tensor.element_type != expected {
+        return Err(SaveError::ElementTypeFileTypeMismatch {
+            tensor_name: tensor.name.clone(),
+            element_type: tensor.element_type,
+            file_type,
+        });
+    }
+
+    Ok(())
+}
+
+/// A single tensor to be written out by [`save_model`].
+pub struct TensorSaveInfo<'a> {
+    /// The tensor's name.
+    pub name: String,
+    /// The tensor's dimensions, outermost first.
+    pub dims: Vec<usize>,
+    /// The ggml element type the tensor's data is stored as.
+    pub element_type: ggml::Type,
+    /// The tensor's raw data.
+    pub data: &'a [u8],
+}
+
+/// Writes a GGML model file: the magic/version header, the hyperparameters (with `file_type`
+/// recorded in the `f16` field), the vocabulary with per-token scores, and each tensor's info
+/// block followed by its data.
+///
+/// If `align_tensor_data` is set, padding is inserted before each tensor's data so that its
+/// offset is a multiple of 32 bytes, matching what the mmap loading path in
+/// [`crate::mmap`] requires, and the file is written as a GGJT container; otherwise it is
+/// written as a GGMF container.
+pub fn save_model(
+    writer: &mut (impl Write + std::io::Seek),
+    hyperparameters: &Hyperparameters,
+    file_type: FileType,
+    vocabulary: &Vocabulary,
+    tensors: &[TensorSaveInfo],
+    align_tensor_data: bool,
+    mut progress_callback: impl FnMut(SaveProgress),
+) -> Result<(), SaveError> {
+    // GGJT is read with every tensor's data padded to 32 bytes; unpadded output is written as
+    // GGMF, which has no such padding.
+    let container_type = if align_tensor_data { ContainerType::Ggjt(3) } else { ContainerType::Ggmf(1) };
+    writer.write_all(&container_type.magic_and_version_bytes())?;
+
+    hyperparameters.write(writer, file_type)?;
+    vocabulary.write(writer)?;
+
+    let mut total_bytes = 0usize;
+    for tensor in tensors {
+        validate_element_type(tensor, file_type)?;
+
+        progress_callback(SaveProgress::TensorWriting { name: &tensor.name });
+
+        let byte_size = write_tensor(writer, tensor, align_tensor_data)?;
+        total_bytes += byte_size;
+
+        progress_callback(SaveProgress::TensorWritten {
+            name: &tensor.name,
+            byte_size,
+        });
+    }
+
+    progress_callback(SaveProgress::Finished { total_bytes });
+
+    Ok(())
+}
+
+/// Writes a single tensor's info block (name, dims, element type) followed by its data,
+/// optionally padding beforehand so the data starts on a 32-byte boundary. Returns the total
+/// number of bytes written, including the info block and any padding.
+///
+/// Also used directly by [`crate::quantize`], which writes tensors one at a time as it
+/// quantizes them rather than building a full `&[TensorSaveInfo]` slice up front.
+pub(crate) fn write_tensor(
+    writer: &mut (impl Write + std::io::Seek),
+    tensor: &TensorSaveInfo,
+    align_tensor_data: bool,
+) -> Result<usize, SaveError> {
+    let name_bytes = tensor.name.as_bytes();
+
+    writer.write_all(&(tensor.dims.len() as u32).to_le_bytes())?;
+    writer.write_all(&(name_bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&i32::from(tensor.element_type).to_le_bytes())?;
+    for &dim in &tensor.dims {
+        writer.write_all(&(dim as u32).to_le_bytes())?;
+    }
+    writer.write_all(name_bytes)?;
+
+    let mut byte_size = 4 + 4 + 4 + tensor.dims.len() * 4 + name_bytes.len();
+
+    if align_tensor_data {
+        let position = writer.stream_position()?;
+        let padding = (32 - (position % 32)) % 32;
+        writer.write_all(&vec![0u8; padding as usize])?;
+        byte_size += padding as usize;
+    }
+
+    writer.write_all(tensor.data)?;
+    byte_size += tensor.data.len();
+
+    Ok(byte_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_a_weight_tensor_whose_element_type_does_not_match_the_file_type() {
+        let data = [0u8; 4];
+        let tensor = TensorSaveInfo {
+            name: "weight".to_owned(),
+            dims: vec![2, 2],
+            element_type: ggml::Type::F32,
+            data: &data,
+        };
+
+        let err = validate_element_type(&tensor, FileType::MostlyQ4_0).unwrap_err();
+        assert!(matches!(err, SaveError::ElementTypeFileTypeMismatch { .. }));
+    }
+
+    #[test]
+    fn accepts_a_1d_tensor_stored_as_f32_regardless_of_the_file_type() {
+        let data = [0u8; 4];
+        let tensor = TensorSaveInfo {
+            name: "norm".to_owned(),
+            dims: vec![4],
+            element_type: ggml::Type::F32,
+            data: &data,
+        };
+
+        validate_element_type(&tensor, FileType::MostlyQ4_0).unwrap();
+    }
+
+    #[test]
+    fn accepts_a_weight_tensor_whose_element_type_matches_the_file_type() {
+        let data = [0u8; 4];
+        let tensor = TensorSaveInfo {
+            name: "weight".to_owned(),
+            dims: vec![2, 2],
+            element_type: ggml::Type::Q4_0,
+            data: &data,
+        };
+
+        validate_element_type(&tensor, FileType::MostlyQ4_0).unwrap();
+    }
+}